@@ -1,27 +1,60 @@
+use cosmwasm_std::{to_json_binary, CosmosMsg, Int256, WasmMsg};
+use cw20::Cw20ReceiveMsg;
+use cw_utils::Expiration;
+
 use crate::prelude::*;
 
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
 pub struct InstantiateMsg {
     pub admin: String,
+    pub config: Config,
 }
 
 fn default_liquidity_portion() -> Decimal256 {
     Decimal256::from_ratio(1u8, 10u8)
 }
 
+pub(crate) fn default_max_publish_delay_seconds() -> u64 {
+    5 * 60
+}
+
+pub(crate) fn default_max_confidence_ratio() -> Decimal256 {
+    Decimal256::percent(5)
+}
+
+pub(crate) fn default_min_dispute_period_seconds() -> u64 {
+    24 * 60 * 60
+}
+
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    AddMarket {
-        params: Box<AddMarketParams>,
-    },
+    /// Create a new market
+    ///
+    /// The admin can always create markets. When [Config::permissionless] is
+    /// set, other wallets can too, by attaching the required creator bond on
+    /// top of the initial liquidity. The bond is returned once a winner is set,
+    /// and slashed if the admin voids the market as invalid.
+    ///
+    /// Markets funded through a CW20 receive hook always take the bonded path,
+    /// since the sender is only claimed by the CW20 contract.
+    AddMarket { params: Box<AddMarketParams> },
     /// Provide liquidity to the liquidity pool
     ///
     /// Due to the nature of the CPMM model, providing liquidity
     /// will generally result in receiving some tokens back as well.
     Provide {
         id: MarketId,
+        /// Frontend or partner that referred this wallet, for attribution only
+        #[serde(default)]
+        referrer: Option<String>,
     },
+    /// Withdraw liquidity from the liquidity pool
+    ///
+    /// Burns the given LP shares and sends back the collateral for all
+    /// complete sets of tokens. Any unmatched tokens are added to the
+    /// sender's positions.
+    WithdrawLiquidity { id: MarketId, shares: LpShare },
     /// Place a bet on an outcome
     Deposit {
         id: MarketId,
@@ -30,28 +63,168 @@ pub enum ExecuteMsg {
         /// provided as liquidity.
         #[serde(default = "default_liquidity_portion")]
         liquidity: Decimal256,
+        /// Fail the deposit if fewer tokens than this would be received.
+        #[serde(default)]
+        min_tokens: Option<Token>,
+        /// Frontend or partner that referred this wallet
+        ///
+        /// Receives [Config::referral_fee_share] of the deposit fee.
+        #[serde(default)]
+        referrer: Option<String>,
     },
     /// Withdraw funds bet on an outcome
+    ///
+    /// For markets with native outcome tokens, the tokens must be attached as coins.
     Withdraw {
         id: MarketId,
         outcome: OutcomeId,
         tokens: Token,
+        /// Fail the withdrawal if less collateral than this would be received, after fees.
+        #[serde(default)]
+        min_collateral: Option<Collateral>,
     },
-    /// Declare the winner of a market
-    SetWinner {
+    /// Convert collateral into a complete set of outcome tokens
+    ///
+    /// Each unit of collateral mints one token of every outcome, without
+    /// touching the pool. Allowed while deposits are open.
+    MintSet { id: MarketId },
+    /// Burn a complete set of outcome tokens for collateral
+    ///
+    /// Burns `amount` tokens of every outcome and sends back the same amount
+    /// of collateral. Allowed until a winner is proposed or the market is voided.
+    ///
+    /// For markets with native outcome tokens, the tokens must be attached as coins.
+    RedeemSet { id: MarketId, amount: Token },
+    /// Send outcome tokens to another wallet
+    ///
+    /// Allowed until a winner is proposed or the market is voided.
+    TransferTokens {
         id: MarketId,
         outcome: OutcomeId,
+        amount: Token,
+        recipient: String,
     },
-    /// Collect winnings from a market
-    Collect {
+    /// Send LP shares to another wallet
+    ///
+    /// Allowed until a winner is proposed or the market is voided.
+    TransferShares {
         id: MarketId,
+        shares: LpShare,
+        recipient: String,
     },
-    /// Appoint a new admin
-    AppointAdmin {
-        addr: String,
+    /// Propose the winner of a market
+    ///
+    /// Can only be called by the arbitrator once deposits have stopped. The
+    /// proposal opens the market's dispute period, after which anyone can
+    /// [ExecuteMsg::FinalizeWinner].
+    ProposeWinner { id: MarketId, outcome: OutcomeId },
+    /// Deprecated alias of [ExecuteMsg::ProposeWinner]
+    ///
+    /// Kept for existing clients. The winner is only proposed, and takes
+    /// effect after [ExecuteMsg::FinalizeWinner].
+    SetWinner { id: MarketId, outcome: OutcomeId },
+    /// Propose the result of a scalar market
+    ///
+    /// Like [ExecuteMsg::ProposeWinner], but with a numeric result. Results
+    /// outside of the market's range are paid out as the nearest bound.
+    ProposeScalarResult {
+        id: MarketId,
+        value: SignedDecimal256,
     },
+    /// Propose the winner of a market from its oracle price
+    ///
+    /// Anyone can call this for a market with a [ResolutionSource] once its
+    /// resolution time has passed. The winner is proposed as by the
+    /// arbitrator, so it can still be overridden during the dispute period.
+    Resolve { id: MarketId },
+    /// Finalize a proposed winner once the dispute period has passed
+    FinalizeWinner { id: MarketId },
+    /// Replace a proposed winner during the dispute period
+    ///
+    /// Admin only. The new winner takes effect immediately.
+    OverrideWinner { id: MarketId, outcome: OutcomeId },
+    /// Replace a proposed scalar result during the dispute period
+    ///
+    /// Admin only. The new result takes effect immediately.
+    OverrideScalarResult {
+        id: MarketId,
+        value: SignedDecimal256,
+    },
+    /// Cancel a market without declaring a winner
+    ///
+    /// Can be called by the admin or the arbitrator before a winner is set.
+    /// Once a winner has been proposed, only the admin can void the market.
+    /// Trading stops and every holder can [ExecuteMsg::Collect] a refund of
+    /// their tokens valued at the current prices.
+    ///
+    /// Only the admin can void a market as `invalid`, which slashes the
+    /// creator bond. Otherwise the bond is returned to the creator. When the
+    /// arbitrator voids, the bond is held until the admin voids the market
+    /// again to return or slash it.
+    VoidMarket {
+        id: MarketId,
+        #[serde(default)]
+        invalid: bool,
+    },
+    /// Collect winnings from a market
+    ///
+    /// For voided markets, this collects the refund instead.
+    ///
+    /// For markets with native outcome tokens, attach the coins to redeem
+    /// them. Without attached coins, internal balances are collected.
+    Collect { id: MarketId },
+    /// Appoint a new admin
+    AppointAdmin { addr: String },
     /// Accept admin privileges
     AcceptAdmin {},
+    /// Replace the global config, admin only
+    UpdateConfig { config: Config },
+    /// Send a market's unclaimed protocol fees to the treasury, admin only
+    ClaimProtocolFees { id: MarketId },
+    /// Collect the sender's referral fees from a market
+    ClaimReferralFees { id: MarketId },
+    /// Add up to `limit` holders from before a migration to the wallet index, admin only
+    ///
+    /// Repeat until it fails with [Error::WalletMarketsBackfilled]. Until then,
+    /// [QueryMsg::WalletPositions] may miss older positions.
+    BackfillWalletMarkets { limit: u32 },
+    /// CW20 receive hook, for markets using CW20 collateral
+    ///
+    /// The embedded message must be an [ExecuteMsg::AddMarket],
+    /// [ExecuteMsg::Provide], [ExecuteMsg::Deposit] or [ExecuteMsg::MintSet],
+    /// and is executed as if sent by the CW20 sender with the tokens attached.
+    Receive(Cw20ReceiveMsg),
+    /// cw1155: send outcome tokens from `from` to `to`
+    ///
+    /// The sender must be `from` or an operator approved by `from`. If `msg`
+    /// is provided, `to` must be a contract and receives a
+    /// [Cw1155ReceiverMsg::Receive] hook. Sending to `from` itself leaves the
+    /// balance unchanged.
+    SendFrom {
+        from: String,
+        to: String,
+        /// See [OutcomeToken] for the format
+        token_id: String,
+        value: Uint128,
+        msg: Option<Binary>,
+    },
+    /// cw1155: send several outcome tokens at once
+    ///
+    /// Like [ExecuteMsg::SendFrom], but triggers a single
+    /// [Cw1155ReceiverMsg::BatchReceive] hook.
+    BatchSendFrom {
+        from: String,
+        to: String,
+        batch: Vec<(String, Uint128)>,
+        msg: Option<Binary>,
+    },
+    /// cw1155: allow an operator to send all of the sender's outcome tokens
+    ApproveAll {
+        operator: String,
+        expires: Option<Expiration>,
+    },
+    /// cw1155: remove an operator's approval
+    RevokeAll { operator: String },
 }
 
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
@@ -62,18 +235,110 @@ pub struct AddMarketParams {
     /// Wallet address. Sets the winner.
     pub arbitrator: String,
     pub outcomes: Vec<OutcomeDef>,
-    /// Denom of collateral for this market.
+    /// Collateral for this market, either a native denom or a CW20 contract.
     ///
     /// Arguably this is unnecessary, it can be picked up from submitted funds.
     /// But it's a double-check, and makes the internal code a bit tidier.
-    pub denom: String,
+    pub denom: Denom,
     /// Given as a ratio, e.g. 0.01 means 1%
     pub deposit_fee: Decimal256,
     pub withdrawal_fee: Decimal256,
     pub withdrawal_stop_date: Timestamp,
     pub deposit_stop_date: Timestamp,
+    /// How long, in seconds, a proposed winner can be disputed before it is final.
+    #[serde(default)]
+    pub dispute_period_seconds: u64,
     /// Which wallet receives house winnings.
     pub house: String,
+    /// Issue bettors' outcome tokens as tokenfactory denoms instead of internal balances.
+    ///
+    /// Denoms have the form `factory/<contract>/m<id>o<outcome>`. Tokens
+    /// received from providing liquidity remain internal balances.
+    ///
+    /// Only the admin can set this. Creating the denoms is paid from the
+    /// contract's balance, so any tokenfactory denom creation fee must be
+    /// covered by the admin rather than the market's collateral.
+    #[serde(default)]
+    pub native_tokens: bool,
+    /// Scale the deposit and withdrawal fees by time remaining until the deposit stop date.
+    ///
+    /// Empty means the fees are flat for the life of the market.
+    #[serde(default)]
+    pub fee_schedule: Vec<FeePoint>,
+    /// Oracle price used to resolve the market with [ExecuteMsg::Resolve]
+    ///
+    /// Only supported for markets with exactly two outcomes.
+    #[serde(default)]
+    pub resolution: Option<ResolutionSource>,
+    /// Make this a scalar market over the given range
+    ///
+    /// Scalar markets have exactly two outcomes, LONG then SHORT. Once the
+    /// arbitrator sets a result `v`, each LONG token pays `(v - min) / (max - min)`
+    /// and each SHORT token pays the rest of one collateral.
+    #[serde(default)]
+    pub scalar: Option<ScalarRange>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ScalarRange {
+    pub min: SignedDecimal256,
+    pub max: SignedDecimal256,
+}
+
+/// Resolve a two outcome market by comparing an oracle price against a strike.
+///
+/// Outcome 0 wins when the price satisfies the comparator, otherwise outcome 1 wins.
+///
+/// Any price published within `max_publish_delay_seconds` of the resolution
+/// time is accepted, and the first [ExecuteMsg::Resolve] call proposes the
+/// winner. If prices on both sides of the strike are published within that
+/// window, the caller effectively picks the outcome, so keep the delay short
+/// relative to the feed's update frequency. The dispute period is the backstop.
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ResolutionSource {
+    /// Oracle contract answering Pyth's `price_feed` query
+    ///
+    /// Must be listed in [Config::oracles].
+    pub oracle: String,
+    /// Hex encoded price feed ID, optionally prefixed with `0x`
+    pub feed_id: String,
+    pub strike: Decimal256,
+    pub comparator: Comparator,
+    /// When the price is observed. Must not be before the deposit stop date.
+    pub resolution_time: Timestamp,
+    /// How long after the resolution time a price can be published and still be used
+    ///
+    /// At most [Config::max_publish_delay_seconds].
+    pub max_publish_delay_seconds: u64,
+    /// Largest allowed ratio of the price's confidence interval to the price
+    ///
+    /// At most [Config::max_confidence_ratio].
+    pub max_confidence_ratio: Decimal256,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    Above,
+    AtOrAbove,
+    Below,
+    AtOrBelow,
+}
+
+/// A point on a market's fee schedule.
+///
+/// Points must be given in strictly decreasing order of `seconds_remaining`.
+/// The multiplier is interpolated linearly between points, and held at the
+/// first or last point's value outside of them.
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct FeePoint {
+    /// Seconds before the deposit stop date
+    pub seconds_remaining: u64,
+    /// Applied to both the deposit and withdrawal fee, e.g. 2 doubles them
+    pub multiplier: Decimal256,
 }
 
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
@@ -93,10 +358,189 @@ pub struct OutcomeDef {
 pub enum QueryMsg {
     /// Returns [GlobalInfo]
     GlobalInfo {},
+    /// Returns [Config]
+    Config {},
     /// Returns [MarketResp]
     Market { id: MarketId },
+    /// Returns [MarketsResp]
+    Markets {
+        start_after: Option<MarketId>,
+        limit: Option<u32>,
+        /// Only return markets with this status
+        ///
+        /// See [MarketsResp::next_start_after] for paging through the results.
+        status: Option<MarketStatus>,
+    },
     /// Returns [PositionsResp]
     Positions { id: MarketId, addr: String },
+    /// Value and profit or loss of a wallet's position
+    ///
+    /// Only internal balances are counted. Outcome tokens issued as native
+    /// denoms and held in the wallet's bank balance are ignored.
+    ///
+    /// Returns [PositionValueResp]
+    PositionValue { id: MarketId, addr: String },
+    /// Referral fees earned by a wallet on each market
+    ///
+    /// Returns [ReferralFeesResp]
+    ReferralFees {
+        referrer: String,
+        start_after: Option<MarketId>,
+        limit: Option<u32>,
+    },
+    /// Wallets holding tokens or LP shares in a market
+    ///
+    /// When `outcome` is provided, only wallets holding tokens for that
+    /// outcome are returned.
+    ///
+    /// Only internal balances are counted, so wallets holding only native
+    /// outcome tokens in their bank balance are not listed.
+    ///
+    /// Returns [HoldersResp]
+    Holders {
+        id: MarketId,
+        outcome: Option<OutcomeId>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Every market where a wallet holds tokens or LP shares
+    ///
+    /// Only internal balances are tracked. Outcome tokens issued as native
+    /// denoms are held in the bank module, so wallets holding only those are
+    /// not listed, and their native tokens are not included in positions.
+    ///
+    /// Returns [WalletPositionsResp]
+    WalletPositions {
+        addr: String,
+        start_after: Option<MarketId>,
+        limit: Option<u32>,
+    },
+    /// Returns [PricesResp]
+    Prices { id: MarketId },
+    /// Historical prices, at most one snapshot per
+    /// [PRICE_HISTORY_BUCKET_SECONDS], oldest first
+    ///
+    /// Returns [PriceHistoryResp]
+    PriceHistory {
+        id: MarketId,
+        start_after: Option<Timestamp>,
+        limit: Option<u32>,
+    },
+    /// Preview the result of a [ExecuteMsg::Deposit] without executing it.
+    ///
+    /// Returns [SimulateDepositResp]
+    SimulateDeposit {
+        id: MarketId,
+        outcome: OutcomeId,
+        /// Collateral to deposit, including fees
+        amount: Collateral,
+        #[serde(default = "default_liquidity_portion")]
+        liquidity: Decimal256,
+        /// Does the deposit include a referrer?
+        #[serde(default)]
+        referred: bool,
+    },
+    /// Preview the result of a [ExecuteMsg::Withdraw] without executing it.
+    ///
+    /// Returns [SimulateWithdrawResp]
+    SimulateWithdraw {
+        id: MarketId,
+        outcome: OutcomeId,
+        tokens: Token,
+    },
+    /// cw1155: outcome token balance of a wallet
+    ///
+    /// Returns [BalanceResp]
+    Balance { owner: String, token_id: String },
+    /// cw1155: balances of several outcome tokens for a wallet
+    ///
+    /// Returns [BatchBalanceResp]
+    BatchBalance {
+        owner: String,
+        token_ids: Vec<String>,
+    },
+    /// cw1155: operators approved by a wallet
+    ///
+    /// Returns [ApprovedForAllResp]
+    ApprovedForAll {
+        owner: String,
+        include_expired: Option<bool>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// cw1155: whether an operator can send a wallet's tokens
+    ///
+    /// Returns [IsApprovedForAllResp]
+    IsApprovedForAll { owner: String, operator: String },
+}
+
+/// Global limits on new markets, set at instantiation and by [ExecuteMsg::UpdateConfig]
+///
+/// These apply to every market, including those created by the admin.
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Config {
+    /// Can wallets other than the admin create markets?
+    pub permissionless: bool,
+    /// Denoms allowed as collateral
+    pub collateral: Vec<CollateralConfig>,
+    /// Highest deposit fee allowed, must be less than 1
+    pub max_deposit_fee: Decimal256,
+    /// Highest withdrawal fee allowed, must be less than 1
+    pub max_withdrawal_fee: Decimal256,
+    /// Most outcomes allowed, at most [MAX_OUTCOMES]
+    pub max_outcomes: u32,
+    /// Shortest time from creation until the deposit stop date
+    pub min_duration_seconds: u64,
+    /// Longest time from creation until the deposit stop date
+    pub max_duration_seconds: u64,
+    /// Share of deposit and withdrawal fees paid to the treasury instead of the house
+    ///
+    /// Applies to markets created after it is set.
+    #[serde(default)]
+    pub protocol_fee_share: Decimal256,
+    /// Receives protocol fees claimed with [ExecuteMsg::ClaimProtocolFees]
+    #[serde(default)]
+    pub treasury: Option<String>,
+    /// Share of the deposit fee paid to the referrer of a deposit
+    ///
+    /// Applies to markets created after it is set. Only wallets in
+    /// [Config::referrers] can be named as referrers, since anyone else could
+    /// refer their own deposits from a second wallet to get a fee rebate.
+    #[serde(default)]
+    pub referral_fee_share: Decimal256,
+    /// Largest [ResolutionSource::max_publish_delay_seconds] allowed
+    #[serde(default = "default_max_publish_delay_seconds")]
+    pub max_publish_delay_seconds: u64,
+    /// Largest [ResolutionSource::max_confidence_ratio] allowed
+    #[serde(default = "default_max_confidence_ratio")]
+    pub max_confidence_ratio: Decimal256,
+    /// Shortest [AddMarketParams::dispute_period_seconds] allowed for markets
+    /// created by wallets other than the admin
+    ///
+    /// Keeps the creator bond in the contract long enough for the admin to
+    /// override or void a bad resolution. Must be nonzero while
+    /// [Config::permissionless] is set.
+    #[serde(default = "default_min_dispute_period_seconds")]
+    pub min_dispute_period_seconds: u64,
+    /// Oracle contracts markets can be resolved against with [ResolutionSource]
+    #[serde(default)]
+    pub oracles: Vec<String>,
+    /// Partner wallets that can be named as the referrer of deposits and liquidity
+    #[serde(default)]
+    pub referrers: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct CollateralConfig {
+    pub denom: Denom,
+    /// Bond required on top of the initial liquidity from wallets other than the admin
+    pub creator_bond: Uint128,
+    /// Smallest initial liquidity for a new market
+    pub min_liquidity: Uint128,
+    /// Largest initial liquidity for a new market
+    pub max_liquidity: Uint128,
 }
 
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
@@ -108,6 +552,52 @@ pub struct GlobalInfo {
 
 pub type MarketResp = StoredMarket;
 
+/// Lifecycle stage of a market, derived from its dates and resolution.
+#[derive(Clone, Copy, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    /// Deposits and withdrawals are both allowed
+    Open,
+    /// Withdrawals have stopped, but deposits are still allowed
+    WithdrawalsClosed,
+    /// All trading has stopped and the market is waiting for a winner
+    AwaitingResolution,
+    /// A winner has been proposed and is within its dispute period
+    WinnerProposed,
+    /// A winner has been set
+    Resolved,
+    /// The market was cancelled and holders can collect refunds
+    Voided,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct MarketsResp {
+    pub markets: Vec<MarketSummary>,
+    /// Where to continue listing, `None` once every market has been looked at
+    ///
+    /// With a status filter, at most [MAX_MARKETS_SCANNED] markets are looked
+    /// at per query, so this can be set even when no markets were returned.
+    pub next_start_after: Option<MarketId>,
+}
+
+/// Subset of [MarketResp] used for listing markets.
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct MarketSummary {
+    pub id: MarketId,
+    pub title: String,
+    pub status: MarketStatus,
+    pub outcomes: Vec<String>,
+    pub denom: Denom,
+    pub pool_size: Collateral,
+    pub deposit_stop_date: Timestamp,
+    pub withdrawal_stop_date: Timestamp,
+    pub winner: Option<OutcomeId>,
+    pub scalar_result: Option<SignedDecimal256>,
+    pub total_wallets: u32,
+}
+
 #[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct OutcomeInfo {
@@ -118,5 +608,226 @@ pub struct OutcomeInfo {
 
 pub type PositionsResp = ShareInfo;
 
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PositionValueResp {
+    /// Collateral paid in through bets, liquidity and minted sets
+    ///
+    /// Transfers move a share of the cost basis and of the withdrawn
+    /// collateral, in proportion to the value transferred at current prices.
+    pub cost_basis: Collateral,
+    /// Collateral paid out from sales, liquidity withdrawals, redeemed sets and winnings
+    pub withdrawn: Collateral,
+    /// What the position is worth now
+    ///
+    /// While the market is unresolved, this is the result of selling every
+    /// token held plus the share of pool tokens at current prices. Once
+    /// resolved or voided, it is the unclaimed payout.
+    pub value: Collateral,
+    /// Collateral withdrawn minus the cost basis
+    pub realized_pnl: Int256,
+    /// Realized profit or loss plus the current value
+    pub total_pnl: Int256,
+    /// Payout for each outcome if it wins, including the share of pool tokens
+    pub payout_if_wins: Vec<Collateral>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct ReferralFeesResp {
+    pub markets: Vec<MarketReferralFees>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct MarketReferralFees {
+    pub id: MarketId,
+    pub denom: Denom,
+    pub unclaimed: Collateral,
+    /// All fees earned, including claimed fees
+    pub total: Collateral,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct HoldersResp {
+    pub holders: Vec<Holder>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Holder {
+    pub addr: Addr,
+    /// Tokens held for each outcome, excluding the share of pool tokens
+    pub outcomes: Vec<Token>,
+    pub shares: LpShare,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WalletPositionsResp {
+    pub positions: Vec<WalletPosition>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct WalletPosition {
+    pub id: MarketId,
+    pub status: MarketStatus,
+    /// Tokens held for each outcome, excluding the share of pool tokens
+    pub outcomes: Vec<Token>,
+    pub shares: LpShare,
+    /// Value of all tokens, including the share of pool tokens
+    ///
+    /// Valued at current prices while trading, and at the final payouts once
+    /// the market is resolved or voided.
+    pub value: Collateral,
+    /// Can winnings or a refund be collected now?
+    pub claimable: bool,
+    pub claimed: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PriceHistoryResp {
+    pub snapshots: Vec<PriceSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PricesResp {
+    pub outcomes: Vec<OutcomePrice>,
+    /// Total collateral backing the market's tokens
+    pub pool_size: Collateral,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct OutcomePrice {
+    pub id: OutcomeId,
+    /// Marginal price in collateral of a single token, also the implied probability.
+    ///
+    /// Prices across all outcomes sum to one.
+    pub price: Decimal256,
+    /// Tokens of this outcome held in the liquidity pool
+    pub pool_tokens: Token,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulateDepositResp {
+    /// Tokens the bettor would receive
+    pub tokens: Token,
+    /// Deposit fee taken from the amount
+    pub fee: Collateral,
+    /// Portion of the fee set aside for the protocol treasury
+    pub protocol_fee: Collateral,
+    /// Deposit fee ratio in effect at the current block time
+    pub fee_rate: Decimal256,
+    /// LP shares the bettor would receive
+    pub lp_shares: LpShare,
+    /// Tokens the house would receive from providing the fee as liquidity
+    pub returned_to_house: Vec<Token>,
+    /// Collateral paid per token received
+    pub effective_price: Decimal256,
+    /// Implied probability of each outcome after the trade
+    pub probabilities: Vec<Decimal256>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulateWithdrawResp {
+    /// Collateral the seller would receive, after fees
+    pub funds: Collateral,
+    /// Withdrawal fee taken from the proceeds
+    pub fee: Collateral,
+    /// Portion of the fee set aside for the protocol treasury
+    pub protocol_fee: Collateral,
+    /// Withdrawal fee ratio in effect at the current block time
+    pub fee_rate: Decimal256,
+    /// LP shares the house would receive from providing the fee as liquidity
+    pub house_lp_shares: LpShare,
+    /// Dust tokens from the sale plus tokens from the fee liquidity, given to the house
+    pub returned_to_house: Vec<Token>,
+    /// Collateral received per token sold
+    pub effective_price: Decimal256,
+    /// Implied probability of each outcome after the trade
+    pub probabilities: Vec<Decimal256>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct BalanceResp {
+    pub balance: Uint128,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct BatchBalanceResp {
+    pub balances: Vec<Uint128>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct ApprovedForAllResp {
+    pub operators: Vec<Approval>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Approval {
+    /// Operator allowed to send the owner's tokens
+    pub spender: String,
+    pub expires: Expiration,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct IsApprovedForAllResp {
+    pub approved: bool,
+}
+
+/// cw1155 hook sent to contracts receiving outcome tokens.
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw1155ReceiverMsg {
+    Receive(Cw1155ReceiveMsg),
+    BatchReceive(Cw1155BatchReceiveMsg),
+}
+
+impl Cw1155ReceiverMsg {
+    pub(crate) fn into_cosmos_msg(self, contract_addr: String) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr,
+            msg: to_json_binary(&self)?,
+            funds: vec![],
+        }
+        .into())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Cw1155ReceiveMsg {
+    /// The wallet that executed the send
+    pub operator: String,
+    /// The wallet the tokens were sent from
+    pub from: Option<String>,
+    pub token_id: String,
+    pub amount: Uint128,
+    pub msg: Binary,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Cw1155BatchReceiveMsg {
+    /// The wallet that executed the send
+    pub operator: String,
+    /// The wallet the tokens were sent from
+    pub from: Option<String>,
+    pub batch: Vec<(String, Uint128)>,
+    pub msg: Binary,
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct MigrateMsg {}
@@ -1,2 +1,24 @@
 pub const CONTRACT_NAME: &str = "levana.finance:predict";
 pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Maximum number of outcomes a market can have.
+///
+/// The CPMM math applies one ratio per other outcome, so this bounds the
+/// rounding error and gas cost of a trade.
+pub const MAX_OUTCOMES: usize = 8;
+
+/// Maximum number of points in a market's fee schedule.
+pub const MAX_FEE_SCHEDULE_POINTS: usize = 10;
+
+/// Width of each bucket in a market's price history, in seconds.
+///
+/// Only the last snapshot within each bucket is kept, which bounds storage to
+/// one entry per bucket per market.
+pub const PRICE_HISTORY_BUCKET_SECONDS: u64 = 60 * 60;
+
+/// Number of items returned from paginated queries when no limit is given.
+pub const DEFAULT_QUERY_LIMIT: u32 = 10;
+/// Maximum number of items returned from paginated queries.
+pub const MAX_QUERY_LIMIT: u32 = 30;
+/// Maximum number of markets a filtered market listing looks at in one query.
+pub const MAX_MARKETS_SCANNED: usize = 100;
@@ -1,4 +1,4 @@
-use cosmwasm_std::Uint256;
+use cosmwasm_std::{Uint256, Uint512};
 
 use crate::prelude::*;
 
@@ -10,12 +10,40 @@ pub struct AddLiquidity {
 }
 
 impl AddLiquidity {
+    /// Credit the liquidity to the given wallet, recording the collateral it paid in.
     pub fn assign_to(
         self,
         storage: &mut dyn Storage,
         market: &mut StoredMarket,
         sender: &Addr,
-        burn_half: bool,
+        deposited: Collateral,
+    ) -> Result<()> {
+        if self.lp.is_zero() {
+            return Ok(());
+        }
+        market.lp_shares += self.lp;
+        self.credit(storage, market, sender, deposited)
+    }
+
+    /// Credit the LP shares and returned tokens to the given wallet.
+    ///
+    /// Unlike [AddLiquidity::assign_to], this does not update the market's
+    /// total LP shares, for liquidity which was already accounted for in the pool.
+    pub fn credit_holder(
+        self,
+        storage: &mut dyn Storage,
+        market: &mut StoredMarket,
+        sender: &Addr,
+    ) -> Result<()> {
+        self.credit(storage, market, sender, Collateral::zero())
+    }
+
+    fn credit(
+        self,
+        storage: &mut dyn Storage,
+        market: &mut StoredMarket,
+        sender: &Addr,
+        deposited: Collateral,
     ) -> Result<()> {
         assert_eq!(market.outcomes.len(), self.returned_to_user.len());
         let AddLiquidity {
@@ -23,45 +51,20 @@ impl AddLiquidity {
             returned_to_user,
             added_to_pool: _,
         } = self;
-        let lp = if burn_half {
-            LpShare(lp.0 / Uint256::from(2u8))
-        } else {
-            lp
-        };
         if lp.is_zero() {
             return Ok(());
         }
-        market.lp_shares += lp;
         let mut share_info = ShareInfo::load(storage, market, sender)?
             .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
-
-        if share_info.shares.is_zero() {
-            market.lp_wallets += 1;
-        }
+        let old_share_info = share_info.clone();
 
         share_info.shares += lp;
-
-        let mut had_tokens = false;
-        let mut has_tokens = false;
-
-        for (outcome, tokens_mut) in share_info.outcomes.iter_mut().enumerate() {
-            let to_add = returned_to_user[outcome];
-            if tokens_mut.is_zero() {
-                if !to_add.is_zero() {
-                    market.outcomes[outcome].wallets += 1;
-                }
-            } else {
-                had_tokens = true;
-            }
-            *tokens_mut += to_add;
-            if !tokens_mut.is_zero() {
-                has_tokens = true;
-            }
+        share_info.deposited += deposited;
+        for (tokens, to_add) in share_info.outcomes.iter_mut().zip(returned_to_user) {
+            *tokens += to_add;
         }
 
-        if !had_tokens && has_tokens {
-            market.total_wallets += 1;
-        }
+        market.update_wallet_counts(&old_share_info, &share_info);
         share_info.save(storage, market, sender)?;
         Ok(())
     }
@@ -83,6 +86,54 @@ pub struct Sell {
     pub returned: Vec<Token>,
 }
 
+/// Result of placing a bet, including fees.
+#[must_use]
+pub struct Deposit {
+    /// Fee taken from the deposit
+    pub fee: Collateral,
+    /// Portion of the fee set aside for the protocol
+    pub protocol_fee: Collateral,
+    /// Portion of the fee set aside for the referrer
+    pub referral_fee: Collateral,
+    /// Liquidity added to the pool from the fee, owned by the house
+    pub house_liquidity: AddLiquidity,
+    /// LP shares minted for the bettor
+    pub lp: LpShare,
+    /// Tokens purchased for the bettor
+    pub tokens: Token,
+}
+
+/// Result of selling tokens, including fees.
+#[must_use]
+pub struct Withdrawal {
+    /// Funds the user will receive, after fees
+    pub funds: Collateral,
+    /// Fee taken from the sale
+    pub fee: Collateral,
+    /// Portion of the fee set aside for the protocol
+    pub protocol_fee: Collateral,
+    /// Dust tokens left over from the sale, given to the house
+    pub returned: Vec<Token>,
+    /// Liquidity added to the pool from the fee, owned by the house
+    pub house_liquidity: AddLiquidity,
+}
+
+/// How a trading fee is divided.
+struct FeeSplit {
+    protocol_fee: Collateral,
+    referral_fee: Collateral,
+    house_liquidity: AddLiquidity,
+}
+
+#[must_use]
+#[derive(Debug)]
+pub struct RemoveLiquidity {
+    /// Collateral freed up by burning complete sets of tokens
+    pub funds: Collateral,
+    /// Unmatched tokens handed back to the liquidity provider
+    pub returned: Vec<Token>,
+}
+
 impl StoredMarket {
     /// Adds liquidity to the market without changing prices of assets.
     pub fn add_liquidity(&mut self, funds: Collateral) -> AddLiquidity {
@@ -109,6 +160,163 @@ impl StoredMarket {
         }
     }
 
+    /// Adds a fee to the pool as liquidity owned by the house.
+    ///
+    /// Half of the LP shares are burned, which benefits all liquidity providers.
+    fn add_fee_liquidity(&mut self, fee: Collateral) -> AddLiquidity {
+        let mut liquidity = self.add_liquidity(fee);
+        liquidity.lp = LpShare(liquidity.lp.0 / Uint256::from(2u8));
+        if !liquidity.lp.is_zero() {
+            self.lp_shares += liquidity.lp;
+        }
+        liquidity
+    }
+
+    /// Splits a fee between the protocol, the referrer if any, and the house.
+    ///
+    /// The protocol and referral portions are held outside the pool until claimed.
+    fn take_fee(&mut self, fee: Collateral, referred: bool) -> FeeSplit {
+        let protocol_fee = fee * self.protocol_fee_share;
+        self.protocol_fees += protocol_fee;
+        let referral_fee = if referred {
+            fee * self.referral_fee_share
+        } else {
+            Collateral::zero()
+        };
+        self.referral_fees += referral_fee;
+        FeeSplit {
+            protocol_fee,
+            referral_fee,
+            house_liquidity: self.add_fee_liquidity(fee - protocol_fee - referral_fee),
+        }
+    }
+
+    /// Place a bet on the given outcome, taking the deposit fee first.
+    ///
+    /// When `referred`, part of the fee is set aside for the referrer.
+    pub fn deposit(
+        &mut self,
+        now: Timestamp,
+        selected_outcome: OutcomeId,
+        amount: Collateral,
+        liquidity: Decimal256,
+        referred: bool,
+    ) -> Result<Deposit> {
+        if liquidity >= Decimal256::one() {
+            return Err(Error::LiquidityShareOfOneOrMore { liquidity });
+        }
+
+        let fee = Decimal256::from_ratio(amount.0, 1u8) * self.deposit_fee_at(now);
+        let fee = Collateral(fee.to_uint_ceil());
+        let FeeSplit {
+            protocol_fee,
+            referral_fee,
+            house_liquidity,
+        } = self.take_fee(fee, referred);
+        let funds = amount.checked_sub(fee)?;
+        let Buy { lp, tokens } = self.buy(selected_outcome, funds, liquidity)?;
+
+        if tokens.is_zero() {
+            return Err(Error::PurchaseTooSmall);
+        }
+
+        Ok(Deposit {
+            fee,
+            protocol_fee,
+            referral_fee,
+            house_liquidity,
+            lp,
+            tokens,
+        })
+    }
+
+    /// Sell the given tokens, taking the withdrawal fee from the proceeds.
+    pub fn withdraw(
+        &mut self,
+        now: Timestamp,
+        selected_outcome: OutcomeId,
+        tokens: Token,
+    ) -> Result<Withdrawal> {
+        let Sell { funds, returned } = self.sell(selected_outcome, tokens)?;
+        let fee = Decimal256::from_ratio(funds.0, 1u8) * self.withdrawal_fee_at(now);
+        let fee = Collateral(fee.to_uint_ceil());
+        let FeeSplit {
+            protocol_fee,
+            referral_fee: _,
+            house_liquidity,
+        } = self.take_fee(fee, false);
+        let funds = funds.checked_sub(fee)?;
+        Ok(Withdrawal {
+            funds,
+            fee,
+            protocol_fee,
+            returned,
+            house_liquidity,
+        })
+    }
+
+    /// Current marginal price of each outcome, also known as its implied probability.
+    ///
+    /// The price of an outcome is inversely proportional to its pool size. Prices
+    /// always sum to exactly one, with any rounding error assigned to the last outcome.
+    pub fn prices(&self) -> Vec<Decimal256> {
+        let largest = self.outcomes.iter().map(|o| o.pool_tokens).max().unwrap();
+        let weights = self
+            .outcomes
+            .iter()
+            .map(|o| largest / o.pool_tokens)
+            .collect::<Vec<_>>();
+        let total = weights
+            .iter()
+            .fold(Decimal256::zero(), |acc, weight| acc + weight);
+
+        let mut prices = weights
+            .into_iter()
+            .map(|weight| weight / total)
+            .collect::<Vec<_>>();
+        let (last, others) = prices.split_last_mut().unwrap();
+        *last = Decimal256::one()
+            - others
+                .iter()
+                .fold(Decimal256::zero(), |acc, price| acc + price);
+        prices
+    }
+
+    /// Removes the given LP shares from the pool without changing prices of assets.
+    ///
+    /// The proportional slice of each outcome's pool tokens is taken out. As many
+    /// complete sets as possible are burned for collateral, and the remainder is
+    /// returned to the liquidity provider.
+    pub fn remove_liquidity(&mut self, shares: LpShare) -> Result<RemoveLiquidity> {
+        if shares >= self.lp_shares {
+            return Err(Error::CannotDropPoolTokensToZero);
+        }
+
+        let mut removed = Vec::new();
+        for outcome in self.outcomes.iter_mut() {
+            let from_pool = Token(
+                outcome
+                    .pool_tokens
+                    .0
+                    .multiply_ratio(shares.0, self.lp_shares.0),
+            );
+            if from_pool == outcome.pool_tokens {
+                return Err(Error::CannotDropPoolTokensToZero);
+            }
+            outcome.pool_tokens -= from_pool;
+            removed.push(from_pool);
+        }
+
+        let funds = removed.iter().copied().min().unwrap();
+        let returned = removed.into_iter().map(|token| token - funds).collect();
+
+        self.lp_shares -= shares;
+        let funds = Collateral(funds.0);
+        self.pool_size -= funds;
+
+        Ok(RemoveLiquidity { funds, returned })
+    }
+
     /// Place a bet on the given outcome.
     ///
     /// Returns the number of tokens purchased
@@ -142,24 +350,28 @@ impl StoredMarket {
         let mut returned = pending_tokens[selected_outcome.usize()];
         pending_tokens[selected_outcome.usize()] = Token::zero();
 
-        // At this point, we haven't violated our invariants. Calculate the
-        // current invariant and the product of non-selected outcomes, updating pool totals
-        // along the way.
-        let mut product_others = Uint256::one();
-        let mut invariant = Uint256::one();
+        // At this point, we haven't violated our invariants. To keep the
+        // product of all pools constant, the selected pool shrinks by the ratio
+        // each other pool grows, updating pool totals along the way.
+        // Full products overflow even 512 bits for large pools with many
+        // outcomes, so we apply one ratio at a time instead. Each step rounds
+        // up in the pool's favor, so the product never decreases.
+        let mut pool_selected = Uint512::from(self.get_outcome(selected_outcome)?.pool_tokens.0);
 
         for (outcome_idx, outcome) in self.outcomes.iter_mut().enumerate() {
-            // Calculate the invariant _before_ adding more tokens.
-            invariant *= outcome.pool_tokens.0;
-
+            let old_pool = outcome.pool_tokens;
             outcome.pool_tokens += pending_tokens[outcome_idx];
 
             if outcome.id != selected_outcome {
-                product_others *= outcome.pool_tokens.0;
+                pool_selected = scale_ceil(pool_selected, old_pool, outcome.pool_tokens)?;
             }
         }
+        // The selected pool only shrinks, so it still fits in 256 bits.
+        let pool_selected = Token(pool_selected.try_into()?);
 
-        let pool_selected = Token(invariant / product_others);
+        if pool_selected.is_zero() {
+            return Err(Error::CannotDropPoolTokensToZero);
+        }
 
         let outcome = self.get_outcome_mut(selected_outcome)?;
         returned += outcome.pool_tokens - pool_selected;
@@ -179,6 +391,73 @@ impl StoredMarket {
     /// [selling/withdrawing collateral](https://github.com/Levana-Protocol/levana-predict/blob/main/docs/selling-withdrawing-collateral.md)
     /// document for more information.
     pub fn sell(&mut self, selected_outcome: OutcomeId, tokens: Token) -> Result<Sell> {
+        // Binary markets have a closed form solution.
+        if self.outcomes.len() == 2 {
+            return self.sell_binary(selected_outcome, tokens);
+        }
+
+        // With more outcomes, selling works by burning complete sets of tokens out
+        // of the pool for collateral, and then paying the pool with the selected
+        // tokens to keep the CPMM invariant intact. We want to burn as many sets as
+        // possible while paying no more than the tokens provided.
+        //
+        // The cost of burning sets only grows as more sets are burned, so we can
+        // binary search for the largest affordable amount. Rounding always favors
+        // the pool.
+        let pool_selected = self.get_outcome(selected_outcome)?.pool_tokens;
+        let smallest_other = self
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.id != selected_outcome)
+            .map(|outcome| outcome.pool_tokens)
+            .min()
+            .ok_or(Error::InsufficientPoolLiquidityForSales)?;
+
+        // We can never burn more sets than the tokens provided, and we must leave
+        // at least one token of every outcome in the pool.
+        let mut low = Uint256::zero();
+        let mut high = tokens.0.min(smallest_other.0 - Uint256::one());
+        while low < high {
+            let mid = low + (high - low + Uint256::one()) / Uint256::from(2u8);
+            if self.sell_cost(selected_outcome, Token(mid))? <= tokens {
+                low = mid;
+            } else {
+                high = mid - Uint256::one();
+            }
+        }
+        let redeemed = Token(low);
+        let sold = self.sell_cost(selected_outcome, redeemed)?;
+
+        // Update the liquidity pool with token count changes.
+        for outcome in self.outcomes.iter_mut() {
+            if outcome.id == selected_outcome {
+                outcome.pool_tokens = pool_selected + sold - redeemed;
+            } else {
+                outcome.pool_tokens -= redeemed;
+            }
+        }
+
+        // Any selected tokens we didn't need are given back as dust.
+        let returned = self
+            .outcomes
+            .iter()
+            .map(|outcome| {
+                if outcome.id == selected_outcome {
+                    tokens - sold
+                } else {
+                    Token::zero()
+                }
+            })
+            .collect();
+
+        let funds = Collateral(redeemed.0);
+        self.pool_size -= funds;
+
+        Ok(Sell { funds, returned })
+    }
+
+    /// Closed form solution for selling on markets with two outcomes.
+    fn sell_binary(&mut self, selected_outcome: OutcomeId, tokens: Token) -> Result<Sell> {
         assert!(self.outcomes.len() == 2);
 
         // We need to swap some of the selected outcome token for the unselected token.
@@ -253,4 +532,35 @@ impl StoredMarket {
 
         Ok(Sell { funds, returned })
     }
+
+    /// Number of selected tokens that must be paid into the pool to burn the given number of complete sets.
+    fn sell_cost(&self, selected_outcome: OutcomeId, redeemed: Token) -> Result<Token> {
+        // After burning, every unselected pool shrinks by the redeemed amount. The
+        // selected pool must then grow by the ratio each of them shrank, rounded up,
+        // to keep the product of all pools constant.
+        let pool_selected = self.get_outcome(selected_outcome)?.pool_tokens;
+        let mut new_pool_selected = Uint512::from(pool_selected.0);
+        for outcome in &self.outcomes {
+            if outcome.id != selected_outcome {
+                new_pool_selected = scale_ceil(
+                    new_pool_selected,
+                    outcome.pool_tokens,
+                    outcome.pool_tokens - redeemed,
+                )?;
+            }
+        }
+        let new_pool_selected = Token(new_pool_selected.try_into()?);
+
+        Ok(new_pool_selected + redeemed - pool_selected)
+    }
+}
+
+/// Multiply a pool size by `numerator / denominator`, rounding up in the pool's favor.
+fn scale_ceil(value: Uint512, numerator: Token, denominator: Token) -> Result<Uint512> {
+    let denominator = Uint512::from(denominator.0);
+    value
+        .checked_mul(numerator.0.into())?
+        .checked_add(denominator - Uint512::one())?
+        .checked_div(denominator)
+        .map_err(|e| StdError::generic_err(e.to_string()).into())
 }
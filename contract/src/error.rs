@@ -1,6 +1,8 @@
 use std::num::TryFromIntError;
 
-use cosmwasm_std::{ConversionOverflowError, OverflowError};
+use cosmwasm_std::{
+    CheckedFromRatioError, ConversionOverflowError, Decimal256RangeExceeded, OverflowError,
+};
 
 use crate::prelude::*;
 
@@ -16,6 +18,10 @@ pub enum Error {
     Overflow(#[from] OverflowError),
     #[error(transparent)]
     TryFromInt(#[from] TryFromIntError),
+    #[error(transparent)]
+    Decimal256RangeExceeded(#[from] Decimal256RangeExceeded),
+    #[error(transparent)]
+    CheckedFromRatio(#[from] CheckedFromRatioError),
     #[error(
         "Multiple assets provided, this contract only supports 0 or 1 assets attached per message"
     )]
@@ -84,6 +90,50 @@ pub enum Error {
     },
     #[error("Winner already set for market {id}")]
     WinnerAlreadySet { id: MarketId },
+    #[error("A winner has already been proposed for market {id}")]
+    WinnerAlreadyProposed { id: MarketId },
+    #[error("No winner has been proposed for market {id}")]
+    NoProposedWinner { id: MarketId },
+    #[error("The dispute period for market {id} is still active. Current time: {now}. Dispute period ends: {dispute_ends}.")]
+    DisputePeriodActive {
+        id: MarketId,
+        now: Timestamp,
+        dispute_ends: Timestamp,
+    },
+    #[error("The dispute period for market {id} is over. Current time: {now}. Dispute period ended: {dispute_ends}.")]
+    DisputePeriodOver {
+        id: MarketId,
+        now: Timestamp,
+        dispute_ends: Timestamp,
+    },
+    #[error("The winner for market {id} has been proposed but not finalized. Dispute period ends: {dispute_ends}.")]
+    WinnerNotFinalized {
+        id: MarketId,
+        dispute_ends: Timestamp,
+    },
+    #[error("Amount must be greater than zero")]
+    ZeroAmount,
+    #[error("Cannot transfer a position to the same wallet")]
+    CannotTransferToSelf,
+    #[error("Invalid token id {token_id}, expected the format market_id/outcome_id")]
+    InvalidTokenId { token_id: String },
+    #[error("Approval expiration is already in the past")]
+    ApprovalExpired,
+    #[error("Denom {denom} is not an outcome token of market {id}")]
+    NotAnOutcomeDenom { id: MarketId, denom: String },
+    #[error("Incorrect outcome tokens attached. Expected: {expected:?}. Received: {received:?}.")]
+    IncorrectOutcomeTokens {
+        expected: Vec<Token>,
+        received: Vec<Token>,
+    },
+    #[error("Market {id} does not issue native outcome tokens")]
+    NotNativeMarket { id: MarketId },
+    #[error("Only AddMarket, Provide, Deposit and MintSet can be sent with CW20 collateral")]
+    UnsupportedCw20Message,
+    #[error("Market {id} has been voided")]
+    MarketVoided { id: MarketId },
+    #[error("Nothing to collect on market {id}")]
+    NothingToCollect { id: MarketId },
     #[error("No winner set for market {id}")]
     NoWinnerSet { id: MarketId },
     #[error("You already claimed winnings for market {id}")]
@@ -97,7 +147,10 @@ pub enum Error {
         idx: String,
         source: std::num::TryFromIntError,
     },
-    #[error("Supported only 2 outcomes, received {total_outcomes}")]
+    #[error(
+        "Markets must have between 2 and {} outcomes, received {total_outcomes}",
+        MAX_OUTCOMES
+    )]
     UnsupportedOutcomes { total_outcomes: usize },
     #[error("Outcome weight cannot be zero")]
     OutcomeWeightCannotBeZero,
@@ -114,8 +167,97 @@ pub enum Error {
     InsufficientPoolLiquidityForSales,
     #[error("Purchase too small and results in no tokens being transferred")]
     PurchaseTooSmall,
+    #[error(
+        "Insufficient LP shares on market {id}. Requested: {requested}. Available: {available}."
+    )]
+    InsufficientShares {
+        id: MarketId,
+        requested: LpShare,
+        available: LpShare,
+    },
+    #[error("The house wallet cannot withdraw all of its LP shares from market {id}")]
+    HouseMustRetainShares { id: MarketId },
     #[error(
         "You specified a liquidity share of {liquidity}. Liquidity share must be less than 1."
     )]
     LiquidityShareOfOneOrMore { liquidity: Decimal256 },
+    #[error(
+        "Slippage tolerance exceeded. Expected at least {expected} tokens, but would receive {actual}."
+    )]
+    DepositSlippageExceeded { expected: Token, actual: Token },
+    #[error(
+        "Slippage tolerance exceeded. Expected at least {expected} collateral, but would receive {actual}."
+    )]
+    WithdrawalSlippageExceeded {
+        expected: Collateral,
+        actual: Collateral,
+    },
+    #[error("Only the admin can create markets")]
+    PermissionlessDisabled,
+    #[error("Only the admin can create markets with native outcome tokens")]
+    NativeTokensAdminOnly,
+    #[error("{denom} is not allowed as collateral")]
+    CollateralNotAllowed { denom: String },
+    #[error("Initial liquidity of {funds} is below the minimum of {min}")]
+    LiquidityBelowMinimum { funds: Collateral, min: Collateral },
+    #[error("Initial liquidity of {funds} is above the maximum of {max}")]
+    LiquidityAboveMaximum { funds: Collateral, max: Collateral },
+    #[error("Market duration of {duration_seconds} seconds is below the minimum of {min} seconds")]
+    DurationTooShort { duration_seconds: u64, min: u64 },
+    #[error("Market duration of {duration_seconds} seconds is above the maximum of {max} seconds")]
+    DurationTooLong { duration_seconds: u64, max: u64 },
+    #[error("Fee of {fee} exceeds the maximum of {max}")]
+    FeeAboveMaximum { fee: Decimal256, max: Decimal256 },
+    #[error("Insufficient funds for the creator bond. Required: {required} on top of the initial liquidity. Provided: {provided}.")]
+    InsufficientBond {
+        required: Collateral,
+        provided: Collateral,
+    },
+    #[error(
+        "Dispute period of {dispute_period_seconds} seconds is below the minimum of {min} seconds"
+    )]
+    DisputePeriodTooShort {
+        dispute_period_seconds: u64,
+        min: u64,
+    },
+    #[error("A wallet cannot refer itself")]
+    SelfReferral,
+    #[error("{referrer} is not a registered referrer")]
+    UnknownReferrer { referrer: Addr },
+    #[error("No treasury is configured to receive protocol fees")]
+    NoTreasury,
+    #[error("Invalid fee schedule: {msg}")]
+    InvalidFeeSchedule { msg: String },
+    #[error("Market {id} is a scalar market and is resolved with a numeric result")]
+    ScalarMarket { id: MarketId },
+    #[error("Market {id} is not a scalar market")]
+    NotScalarMarket { id: MarketId },
+    #[error("Invalid scalar range: {msg}")]
+    InvalidScalarRange { msg: String },
+    #[error("Invalid resolution source: {msg}")]
+    InvalidResolutionSource { msg: String },
+    #[error("Market {id} has no resolution source")]
+    NoResolutionSource { id: MarketId },
+    #[error("Market {id} cannot be resolved before {resolution_time}. Current time: {now}.")]
+    ResolutionTimeNotReached {
+        id: MarketId,
+        now: Timestamp,
+        resolution_time: Timestamp,
+    },
+    #[error(
+        "Oracle price published at {publish_time} is outside of the window from {start} to {end}"
+    )]
+    PriceOutsidePublishWindow {
+        publish_time: i64,
+        start: Timestamp,
+        end: Timestamp,
+    },
+    #[error("Oracle price confidence ratio {ratio} is above the maximum of {max}")]
+    PriceConfidenceTooWide { ratio: Decimal256, max: Decimal256 },
+    #[error("Oracle returned a non-positive price: {price}")]
+    InvalidOraclePrice { price: i64 },
+    #[error("Invalid config: {msg}")]
+    InvalidConfig { msg: String },
+    #[error("Every holder is already in the wallet index")]
+    WalletMarketsBackfilled,
 }
@@ -1,8 +1,13 @@
-use cosmwasm_std::{BankMsg, CosmosMsg, Event, Uint256};
+use cosmwasm_std::{from_json, CosmosMsg, Event, Uint256};
+use cw20::Cw20ReceiveMsg;
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
 
 use crate::{
-    cpmm::{Buy, Sell},
+    cpmm::{Deposit, RemoveLiquidity, Withdrawal},
+    oracle,
     prelude::*,
+    tokenfactory,
     util::{assert_is_admin, Funds},
 };
 
@@ -16,46 +21,183 @@ pub fn execute(
     sanity(deps.storage, &env);
     let funds = Funds::from_message_info(&info)?;
 
-    let res = match msg {
+    let res = execute_msg(&mut deps, &env, info, msg, funds)?;
+
+    sanity(deps.storage, &env);
+    Ok(res)
+}
+
+fn execute_msg(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+    funds: Funds,
+) -> Result<Response> {
+    match msg {
+        ExecuteMsg::Receive(msg) => {
+            funds.require_none()?;
+            receive_cw20(deps, env, info, msg)
+        }
         ExecuteMsg::AddMarket { params } => {
-            assert_is_admin(deps.storage, &info)?;
-            add_market(&mut deps, &env, *params, funds)
+            let is_admin = info.sender == ADMIN.load(deps.storage)?;
+            add_market(deps, env, info, *params, funds, is_admin)
+        }
+        ExecuteMsg::Provide { id, referrer } => provide(deps, env, info, id, funds, referrer),
+        ExecuteMsg::WithdrawLiquidity { id, shares } => {
+            funds.require_none()?;
+            withdraw_liquidity(deps, env, info, id, shares)
         }
-        ExecuteMsg::Provide { id } => provide(&mut deps, &env, info, id, funds),
         ExecuteMsg::Deposit {
             id,
             outcome,
             liquidity,
-        } => deposit(&mut deps, &env, info, id, outcome, funds, liquidity),
+            min_tokens,
+            referrer,
+        } => deposit(
+            deps, env, info, id, outcome, funds, liquidity, min_tokens, referrer,
+        ),
         ExecuteMsg::Withdraw {
             id,
             outcome,
             tokens,
+            min_collateral,
+        } => withdraw(deps, env, info, id, outcome, tokens, funds, min_collateral),
+        ExecuteMsg::MintSet { id } => mint_set(deps, env, info, id, funds),
+        ExecuteMsg::RedeemSet { id, amount } => redeem_set(deps, env, info, id, amount, funds),
+        ExecuteMsg::TransferTokens {
+            id,
+            outcome,
+            amount,
+            recipient,
+        } => {
+            funds.require_none()?;
+            transfer_tokens(deps, info, id, outcome, amount, recipient)
+        }
+        ExecuteMsg::TransferShares {
+            id,
+            shares,
+            recipient,
         } => {
             funds.require_none()?;
-            withdraw(&mut deps, &env, info, id, outcome, tokens)
+            transfer_shares(deps, info, id, shares, recipient)
+        }
+        ExecuteMsg::ProposeWinner { id, outcome } | ExecuteMsg::SetWinner { id, outcome } => {
+            funds.require_none()?;
+            propose_winner(deps, env, info, id, Some(outcome), None)
+        }
+        ExecuteMsg::ProposeScalarResult { id, value } => {
+            funds.require_none()?;
+            propose_winner(deps, env, info, id, None, Some(value))
+        }
+        ExecuteMsg::Resolve { id } => {
+            funds.require_none()?;
+            resolve(deps, env, id)
+        }
+        ExecuteMsg::FinalizeWinner { id } => {
+            funds.require_none()?;
+            finalize_winner(deps, env, id)
+        }
+        ExecuteMsg::OverrideWinner { id, outcome } => {
+            funds.require_none()?;
+            assert_is_admin(deps.storage, &info)?;
+            override_winner(deps, env, id, Some(outcome), None)
         }
-        ExecuteMsg::SetWinner { id, outcome } => {
+        ExecuteMsg::OverrideScalarResult { id, value } => {
             funds.require_none()?;
-            set_winner(&mut deps, &env, info, id, outcome)
+            assert_is_admin(deps.storage, &info)?;
+            override_winner(deps, env, id, None, Some(value))
         }
-        ExecuteMsg::Collect { id } => {
+        ExecuteMsg::VoidMarket { id, invalid } => {
             funds.require_none()?;
-            collect(&mut deps, info, id)
+            void_market(deps, env, info, id, invalid)
         }
+        ExecuteMsg::Collect { id } => collect(deps, env, info, id, funds),
         ExecuteMsg::AppointAdmin { addr } => {
             funds.require_none()?;
             assert_is_admin(deps.storage, &info)?;
-            appoint_admin(&mut deps, addr)
+            appoint_admin(deps, addr)
         }
         ExecuteMsg::AcceptAdmin {} => {
             funds.require_none()?;
-            accept_admin(&mut deps, info)
+            accept_admin(deps, info)
+        }
+        ExecuteMsg::UpdateConfig { config } => {
+            funds.require_none()?;
+            assert_is_admin(deps.storage, &info)?;
+            update_config(deps, config)
+        }
+        ExecuteMsg::ClaimProtocolFees { id } => {
+            funds.require_none()?;
+            assert_is_admin(deps.storage, &info)?;
+            claim_protocol_fees(deps, id)
+        }
+        ExecuteMsg::ClaimReferralFees { id } => {
+            funds.require_none()?;
+            claim_referral_fees(deps, info, id)
+        }
+        ExecuteMsg::BackfillWalletMarkets { limit } => {
+            funds.require_none()?;
+            assert_is_admin(deps.storage, &info)?;
+            backfill_wallet_markets(deps, limit)
+        }
+        ExecuteMsg::SendFrom {
+            from,
+            to,
+            token_id,
+            value,
+            msg,
+        } => {
+            funds.require_none()?;
+            send_from(deps, env, info, from, to, token_id, value, msg)
         }
-    }?;
+        ExecuteMsg::BatchSendFrom {
+            from,
+            to,
+            batch,
+            msg,
+        } => {
+            funds.require_none()?;
+            batch_send_from(deps, env, info, from, to, batch, msg)
+        }
+        ExecuteMsg::ApproveAll { operator, expires } => {
+            funds.require_none()?;
+            approve_all(deps, env, info, operator, expires)
+        }
+        ExecuteMsg::RevokeAll { operator } => {
+            funds.require_none()?;
+            revoke_all(deps, info, operator)
+        }
+    }
+}
 
-    sanity(deps.storage, &env);
-    Ok(res)
+/// Handle collateral sent by a CW20 contract, executing the embedded message on behalf of the sender.
+fn receive_cw20(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    Cw20ReceiveMsg {
+        sender,
+        amount,
+        msg,
+    }: Cw20ReceiveMsg,
+) -> Result<Response> {
+    let funds = Funds::Funds {
+        denom: Denom::Cw20(info.sender),
+        amount,
+    };
+    let info = MessageInfo {
+        sender: deps.api.addr_validate(&sender)?,
+        funds: vec![],
+    };
+    match from_json(msg)? {
+        // The sender is only claimed by the CW20 contract, so it never grants admin rights
+        ExecuteMsg::AddMarket { params } => add_market(deps, env, info, *params, funds, false),
+        msg @ (ExecuteMsg::Provide { .. }
+        | ExecuteMsg::Deposit { .. }
+        | ExecuteMsg::MintSet { .. }) => execute_msg(deps, env, info, msg, funds),
+        _ => Err(Error::UnsupportedCw20Message),
+    }
 }
 
 pub struct InitialOutcomes {
@@ -95,6 +237,8 @@ pub(crate) fn initial_outcomes(
             label,
             pool_tokens: initial_amount,
             wallets: if initial_amount.0 == funds.0 { 0 } else { 1 },
+            native_denom: None,
+            minted: Token::zero(),
         });
         returned.push(Token(funds.0) - initial_amount);
     }
@@ -104,9 +248,101 @@ pub(crate) fn initial_outcomes(
     })
 }
 
+/// Check that fee schedule points are in order, returning the largest multiplier.
+fn validate_fee_schedule(fee_schedule: &[FeePoint]) -> Result<Decimal256> {
+    if fee_schedule.len() > MAX_FEE_SCHEDULE_POINTS {
+        return Err(Error::InvalidFeeSchedule {
+            msg: format!("at most {MAX_FEE_SCHEDULE_POINTS} points are allowed"),
+        });
+    }
+    if fee_schedule
+        .windows(2)
+        .any(|window| window[0].seconds_remaining <= window[1].seconds_remaining)
+    {
+        return Err(Error::InvalidFeeSchedule {
+            msg: "points must be in decreasing order of seconds_remaining".to_owned(),
+        });
+    }
+    Ok(fee_schedule
+        .iter()
+        .map(|point| point.multiplier)
+        .max()
+        .unwrap_or_else(Decimal256::one))
+}
+
+/// Check that a scalar range can be used for a market with the given outcomes.
+fn validate_scalar(scalar: &ScalarRange, total_outcomes: usize) -> Result<()> {
+    let msg = if total_outcomes != 2 {
+        "scalar markets must have exactly two outcomes, LONG then SHORT"
+    } else if scalar.min >= scalar.max {
+        "min must be below max"
+    } else if scalar.max.checked_sub(scalar.min).is_err() {
+        "max - min must not overflow"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidScalarRange {
+        msg: msg.to_owned(),
+    })
+}
+
+/// Check that a resolution source can resolve a market with the given outcomes.
+fn validate_resolution(
+    api: &dyn Api,
+    config: &Config,
+    ResolutionSource {
+        oracle,
+        feed_id,
+        strike,
+        comparator,
+        resolution_time,
+        max_publish_delay_seconds,
+        max_confidence_ratio,
+    }: ResolutionSource,
+    total_outcomes: usize,
+    deposit_stop_date: Timestamp,
+) -> Result<StoredResolutionSource> {
+    let oracle = api.addr_validate(&oracle)?;
+    let msg = if !config
+        .oracles
+        .iter()
+        .any(|allowed| oracle == allowed.as_str())
+    {
+        "oracle is not in the configured list of oracles"
+    } else if total_outcomes != 2 {
+        "only markets with two outcomes can be resolved by an oracle"
+    } else if !is_hex(feed_id.strip_prefix("0x").unwrap_or(&feed_id)) {
+        "feed_id must be hex encoded"
+    } else if max_publish_delay_seconds > config.max_publish_delay_seconds {
+        "max_publish_delay_seconds is above the configured limit"
+    } else if max_confidence_ratio > config.max_confidence_ratio {
+        "max_confidence_ratio is above the configured limit"
+    } else if resolution_time < deposit_stop_date {
+        "resolution_time must not be before the deposit stop date"
+    } else {
+        return Ok(StoredResolutionSource {
+            oracle,
+            feed_id,
+            strike,
+            comparator,
+            resolution_time,
+            max_publish_delay_seconds,
+            max_confidence_ratio,
+        });
+    };
+    Err(Error::InvalidResolutionSource {
+        msg: msg.to_owned(),
+    })
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 fn add_market(
     deps: &mut DepsMut,
     env: &Env,
+    info: MessageInfo,
     AddMarketParams {
         title,
         description,
@@ -117,9 +353,15 @@ fn add_market(
         withdrawal_fee,
         withdrawal_stop_date,
         deposit_stop_date,
+        dispute_period_seconds,
         house,
+        native_tokens,
+        fee_schedule,
+        resolution,
+        scalar,
     }: AddMarketParams,
     funds: Funds,
+    is_admin: bool,
 ) -> Result<Response> {
     if env.block.time >= withdrawal_stop_date {
         return Err(Error::WithdrawalStopDateInPast {
@@ -135,17 +377,91 @@ fn add_market(
     }
 
     let total_outcomes = outcomes.len();
-    if !total_outcomes == 2 {
+    if !(2..=MAX_OUTCOMES).contains(&total_outcomes) {
         return Err(Error::UnsupportedOutcomes { total_outcomes });
     }
 
-    let funds = funds.require_funds(&denom)?;
+    let config = CONFIG.load(deps.storage)?;
+    if let Some(scalar) = &scalar {
+        validate_scalar(scalar, total_outcomes)?;
+    }
+    let resolution = match resolution {
+        Some(_) if scalar.is_some() => {
+            return Err(Error::InvalidResolutionSource {
+                msg: "scalar markets cannot be resolved by an oracle".to_owned(),
+            });
+        }
+        Some(resolution) => Some(validate_resolution(
+            deps.api,
+            &config,
+            resolution,
+            total_outcomes,
+            deposit_stop_date,
+        )?),
+        None => None,
+    };
+
+    denom.validate(deps.api)?;
+    let collateral = config.get_collateral(&denom)?;
+    let provided = funds.require_funds(&denom)?;
+    let creator_bond = if is_admin {
+        None
+    } else {
+        if !config.permissionless {
+            return Err(Error::PermissionlessDisabled);
+        }
+        if native_tokens {
+            return Err(Error::NativeTokensAdminOnly);
+        }
+        if dispute_period_seconds < config.min_dispute_period_seconds {
+            return Err(Error::DisputePeriodTooShort {
+                dispute_period_seconds,
+                min: config.min_dispute_period_seconds,
+            });
+        }
+        let required = Collateral(collateral.creator_bond.into());
+        if provided.0 <= required.0 {
+            return Err(Error::InsufficientBond { required, provided });
+        }
+        Some(CreatorBond {
+            creator: info.sender,
+            amount: required,
+        })
+    };
+    let funds = match &creator_bond {
+        Some(bond) => provided - bond.amount,
+        None => provided,
+    };
+    let max_multiplier = validate_fee_schedule(&fee_schedule)?;
+    config.assert_market_allowed(
+        collateral,
+        deposit_fee * max_multiplier,
+        withdrawal_fee * max_multiplier,
+        total_outcomes,
+        funds,
+        deposit_stop_date.seconds() - env.block.time.seconds(),
+    )?;
+
     let id = LAST_MARKET_ID
         .may_load(deps.storage)?
         .map_or_else(MarketId::one, MarketId::next);
     LAST_MARKET_ID.save(deps.storage, &id)?;
     let arbitrator = deps.api.addr_validate(&arbitrator)?;
-    let InitialOutcomes { outcomes, returned } = initial_outcomes(outcomes, funds)?;
+    let InitialOutcomes {
+        mut outcomes,
+        returned,
+    } = initial_outcomes(outcomes, funds)?;
+
+    let mut create_denoms = vec![];
+    if native_tokens {
+        for outcome in &mut outcomes {
+            outcome.native_denom = Some(tokenfactory::denom(&env.contract.address, id, outcome.id));
+            create_denoms.push(tokenfactory::create_denom(
+                &env.contract.address,
+                tokenfactory::subdenom(id, outcome.id),
+            ));
+        }
+    }
 
     // Initial LP share value is completely arbitrary. We take the largest token
     // allocation and multiply by a million, chosen arbitrarily to avoid both overflows
@@ -165,29 +481,47 @@ fn add_market(
         denom,
         deposit_fee,
         withdrawal_fee,
+        fee_schedule,
+        resolution,
+        scalar,
         pool_size: funds,
         deposit_stop_date,
         withdrawal_stop_date,
         winner: None,
+        scalar_result: None,
+        dispute_period_seconds,
+        proposed_winner: None,
+        void_prices: None,
         house,
         lp_shares,
         // Always have 1 wallet: the house
         total_wallets: 1,
         lp_wallets: 1,
+        stats: MarketStats::default(),
+        creator_bond,
+        protocol_fee_share: config.protocol_fee_share,
+        protocol_fees: Collateral::zero(),
+        referral_fee_share: config.referral_fee_share,
+        referral_fees: Collateral::zero(),
     };
     MARKETS.save(deps.storage, id, &market)?;
+    market.snapshot_prices(deps.storage, env.block.time)?;
 
     ShareInfo {
         outcomes: returned,
         shares: lp_shares,
         claimed_winnings: false,
+        deposited: funds,
+        withdrawn: Collateral::zero(),
     }
     .save(deps.storage, &market, &market.house)?;
 
     Ok(Response::new()
-        .add_event(Event::new("add-market").add_attribute("market-id", id.0.to_string())))
+        .add_event(Event::new("add-market").add_attribute("market-id", id.0.to_string()))
+        .add_messages(create_denoms))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn deposit(
     deps: &mut DepsMut,
     env: &Env,
@@ -196,76 +530,136 @@ fn deposit(
     outcome: OutcomeId,
     funds: Funds,
     liquidity: Decimal256,
+    min_tokens: Option<Token>,
+    referrer: Option<String>,
 ) -> Result<Response> {
     let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_deposits_open(env.block.time)?;
+    let referrer = validate_referrer(deps.as_ref(), &info.sender, referrer)?;
 
-    if liquidity >= Decimal256::one() {
-        return Err(Error::LiquidityShareOfOneOrMore { liquidity });
-    }
+    let deposit_amount = funds.require_funds(&market.denom)?;
+    let Deposit {
+        fee,
+        protocol_fee,
+        referral_fee,
+        house_liquidity,
+        lp,
+        tokens,
+    } = market.deposit(
+        env.block.time,
+        outcome,
+        deposit_amount,
+        liquidity,
+        referrer.is_some(),
+    )?;
 
-    if env.block.time >= market.deposit_stop_date {
-        return Err(Error::DepositsStopped {
-            id,
-            now: env.block.time,
-            deposit_stop_date: market.deposit_stop_date,
-        });
+    if let Some(min_tokens) = min_tokens {
+        if tokens < min_tokens {
+            return Err(Error::DepositSlippageExceeded {
+                expected: min_tokens,
+                actual: tokens,
+            });
+        }
     }
 
-    let deposit_amount = funds.require_funds(&market.denom)?;
-    let fee = Decimal256::from_ratio(deposit_amount.0, 1u8) * market.deposit_fee;
-    let fee = Collateral(fee.to_uint_ceil());
     let house = market.house.clone();
-    market
-        .add_liquidity(fee)
-        .assign_to(deps.storage, &mut market, &house, true)?;
-    let funds = deposit_amount.checked_sub(fee)?;
-    let Buy { lp, tokens } = market.buy(outcome, funds, liquidity)?;
-
-    if tokens.is_zero() {
-        return Err(Error::PurchaseTooSmall);
-    }
+    house_liquidity.credit_holder(deps.storage, &mut market, &house)?;
 
     let mut share_info = ShareInfo::load(deps.storage, &market, &info.sender)?
         .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
+    let old_share_info = share_info.clone();
 
-    assert_eq!(share_info.outcomes.len(), market.outcomes.len());
-
-    if !lp.is_zero() {
-        if share_info.shares.is_zero() {
-            market.lp_wallets += 1;
-            if !share_info.has_tokens() {
-                market.total_wallets += 1;
-            }
-        }
-        share_info.shares += lp;
+    share_info.shares += lp;
+    share_info.deposited += deposit_amount;
+    let mut msgs = vec![];
+    if market.is_native() {
+        msgs.push(mint_outcome_tokens(
+            env,
+            &mut market,
+            outcome,
+            tokens,
+            &info.sender,
+        )?);
+    } else {
+        *share_info.get_outcome_mut(id, outcome)? += tokens;
     }
 
-    let had_any_tokens = share_info.has_tokens() || !share_info.shares.is_zero();
+    market.update_wallet_counts(&old_share_info, &share_info);
+    share_info.save(deps.storage, &market, &info.sender)?;
 
-    let outcome_tokens = share_info.get_outcome_mut(id, outcome).unwrap();
-    let had_these_tokens = !outcome_tokens.is_zero();
+    market.stats.deposit_volume += deposit_amount;
+    market.stats.deposit_fees += fee;
+    market.stats.protocol_fees += protocol_fee;
+    market.stats.referral_fees += referral_fee;
+    market.stats.trades += 1;
+    market.stats.last_trade = Some(env.block.time);
+    market.snapshot_prices(deps.storage, env.block.time)?;
+    MARKETS.save(deps.storage, id, &market)?;
 
-    if !had_any_tokens {
-        market.total_wallets += 1;
+    let mut res = Response::new()
+        .add_event(
+            Event::new("deposit")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("outcome-id", outcome.to_string())
+                .add_attribute("tokens", tokens.to_string())
+                .add_attribute("deposit-amount", deposit_amount.to_string())
+                .add_attribute("fee", fee.to_string())
+                .add_attribute("protocol-fee", protocol_fee.to_string()),
+        )
+        .add_messages(msgs);
+    if let Some(referrer) = referrer {
+        res = res.add_event(credit_referrer(
+            deps.storage,
+            id,
+            &referrer,
+            &info.sender,
+            referral_fee,
+        )?);
     }
+    Ok(res)
+}
 
-    if !had_these_tokens {
-        market.get_outcome_mut(outcome)?.wallets += 1;
+/// Validate an optional referrer, which must be registered in the config and may not be the sender.
+fn validate_referrer(deps: Deps, sender: &Addr, referrer: Option<String>) -> Result<Option<Addr>> {
+    let Some(referrer) = referrer else {
+        return Ok(None);
+    };
+    let referrer = deps.api.addr_validate(&referrer)?;
+    if referrer == sender {
+        return Err(Error::SelfReferral);
     }
+    let config = CONFIG.load(deps.storage)?;
+    if !config
+        .referrers
+        .iter()
+        .any(|allowed| referrer == allowed.as_str())
+    {
+        return Err(Error::UnknownReferrer { referrer });
+    }
+    Ok(Some(referrer))
+}
 
-    *outcome_tokens += tokens;
-    share_info.save(deps.storage, &market, &info.sender)?;
-
-    MARKETS.save(deps.storage, id, &market)?;
-
-    Ok(Response::new().add_event(
-        Event::new("deposit")
-            .add_attribute("market-id", id.to_string())
-            .add_attribute("outcome-id", outcome.to_string())
-            .add_attribute("tokens", tokens.to_string())
-            .add_attribute("deposit-amount", deposit_amount.to_string())
-            .add_attribute("fee", fee.to_string()),
-    ))
+/// Add a referral fee to the referrer's balance and return the attribution event.
+fn credit_referrer(
+    store: &mut dyn Storage,
+    id: MarketId,
+    referrer: &Addr,
+    wallet: &Addr,
+    fee: Collateral,
+) -> Result<Event> {
+    if !fee.is_zero() {
+        let mut fees = REFERRAL_FEES
+            .may_load(store, (referrer, id))?
+            .unwrap_or_default();
+        fees.unclaimed += fee;
+        fees.total += fee;
+        REFERRAL_FEES.save(store, (referrer, id), &fees)?;
+    }
+    Ok(Event::new("referral")
+        .add_attribute("market-id", id.to_string())
+        .add_attribute("referrer", referrer.to_string())
+        .add_attribute("wallet", wallet.to_string())
+        .add_attribute("fee", fee.to_string()))
 }
 
 fn provide(
@@ -274,21 +668,16 @@ fn provide(
     info: MessageInfo,
     id: MarketId,
     funds: Funds,
+    referrer: Option<String>,
 ) -> Result<Response> {
     let mut market = StoredMarket::load(deps.storage, id)?;
-
-    if env.block.time >= market.deposit_stop_date {
-        return Err(Error::DepositsStopped {
-            id,
-            now: env.block.time,
-            deposit_stop_date: market.deposit_stop_date,
-        });
-    }
+    market.assert_deposits_open(env.block.time)?;
+    let referrer = validate_referrer(deps.as_ref(), &info.sender, referrer)?;
 
     let deposit_amount = funds.require_funds(&market.denom)?;
     let add_liquidity = market.add_liquidity(deposit_amount);
 
-    let res = Response::new().add_event(
+    let mut res = Response::new().add_event(
         Event::new("provide")
             .add_attribute("market-id", id.to_string())
             .add_attribute("deposit-amount", deposit_amount.to_string())
@@ -303,64 +692,132 @@ fn provide(
             ),
     );
 
-    add_liquidity.assign_to(deps.storage, &mut market, &info.sender, false)?;
+    add_liquidity.assign_to(deps.storage, &mut market, &info.sender, deposit_amount)?;
+    market.snapshot_prices(deps.storage, env.block.time)?;
     MARKETS.save(deps.storage, market.id, &market)?;
 
+    // Providing liquidity pays no fee, so referrals are attribution only
+    if let Some(referrer) = referrer {
+        res = res.add_event(credit_referrer(
+            deps.storage,
+            id,
+            &referrer,
+            &info.sender,
+            Collateral::zero(),
+        )?);
+    }
     Ok(res)
 }
 
-fn withdraw(
+fn withdraw_liquidity(
     deps: &mut DepsMut,
     env: &Env,
     info: MessageInfo,
     id: MarketId,
-    outcome: OutcomeId,
-    tokens: Token,
+    shares: LpShare,
 ) -> Result<Response> {
     let mut market = StoredMarket::load(deps.storage, id)?;
-
-    if env.block.time >= market.withdrawal_stop_date {
-        return Err(Error::WithdrawalsStopped {
-            id,
-            now: env.block.time,
-            withdrawal_stop_date: market.withdrawal_stop_date,
-        });
-    }
+    market.assert_withdrawals_open(env.block.time)?;
 
     let mut share_info = ShareInfo::load(deps.storage, &market, &info.sender)?
         .ok_or(Error::NoPositionsOnMarket { id })?;
 
-    let user_tokens = share_info.get_outcome_mut(id, outcome)?;
-    if user_tokens.is_zero() {
-        return Err(Error::NoTokensFound { id, outcome });
-    }
-
-    if *user_tokens < tokens {
-        return Err(Error::InsufficientTokens {
+    if shares.is_zero() || share_info.shares < shares {
+        return Err(Error::InsufficientShares {
             id,
-            outcome,
-            requested: tokens,
-            available: *user_tokens,
+            requested: shares,
+            available: share_info.shares,
         });
     }
 
-    *user_tokens -= tokens;
+    if info.sender == market.house && share_info.shares == shares {
+        return Err(Error::HouseMustRetainShares { id });
+    }
 
-    let Sell { funds, returned } = market.sell(outcome, tokens)?;
+    let RemoveLiquidity { funds, returned } = market.remove_liquidity(shares)?;
 
-    if share_info.get_outcome(&market, outcome, false)?.is_zero() {
-        market.get_outcome_mut(outcome)?.wallets -= 1;
-        if !share_info.has_tokens() && share_info.shares.is_zero() {
-            market.total_wallets -= 1;
-        }
+    let old_share_info = share_info.clone();
+    share_info.shares -= shares;
+    share_info.withdrawn += funds;
+    for (tokens, returned) in share_info.outcomes.iter_mut().zip(returned.iter()) {
+        *tokens += *returned;
     }
+    market.update_wallet_counts(&old_share_info, &share_info);
 
     share_info.save(deps.storage, &market, &info.sender)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    let res = Response::new().add_event(
+        Event::new("withdraw-liquidity")
+            .add_attribute("market-id", id.to_string())
+            .add_attribute("shares", shares.to_string())
+            .add_attribute("withdrawal", funds.to_string())
+            .add_attribute("new-user-tokens", format!("{:?}", returned)),
+    );
+
+    Ok(if funds.is_zero() {
+        res
+    } else {
+        res.add_message(market.denom.send(info.sender, funds)?)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn withdraw(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    outcome: OutcomeId,
+    tokens: Token,
+    funds: Funds,
+    min_collateral: Option<Collateral>,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_withdrawals_open(env.block.time)?;
+
+    // Internal balances are debited up front and saved once the payout is known
+    let (burn_msgs, seller) = if market.is_native() {
+        market.get_outcome(outcome)?;
+        let mut expected = vec![Token::zero(); market.outcomes.len()];
+        expected[outcome.usize()] = tokens;
+        let (received, msgs) = burn_outcome_tokens(env, &market, funds)?;
+        if received != expected {
+            return Err(Error::IncorrectOutcomeTokens { expected, received });
+        }
+        market.return_minted(&received);
+        (msgs, None)
+    } else {
+        funds.require_none()?;
+        let share_info = debit_tokens(deps.storage, &mut market, &info.sender, outcome, tokens)?;
+        (vec![], Some(share_info))
+    };
+
+    let Withdrawal {
+        funds,
+        fee,
+        protocol_fee,
+        returned,
+        house_liquidity,
+    } = market.withdraw(env.block.time, outcome, tokens)?;
+    match seller {
+        Some(mut share_info) => {
+            share_info.withdrawn += funds;
+            share_info.save(deps.storage, &market, &info.sender)?;
+        }
+        None => record_collateral(
+            deps.storage,
+            &market,
+            &info.sender,
+            Collateral::zero(),
+            funds,
+        )?,
+    }
 
     // We sent the returned dust to the house wallet instead to avoid
     // leaving users with confusing small amounts.
+    let house = market.house.clone();
     if returned.iter().any(|token| !token.is_zero()) {
-        let house = market.house.clone();
         let mut share_info = ShareInfo::load(deps.storage, &market, &house)?
             .expect("Must have a holder record for the house");
         for (idx, returned) in returned.into_iter().enumerate() {
@@ -374,13 +831,23 @@ fn withdraw(
         share_info.save(deps.storage, &market, &house)?;
     }
 
-    let fee = Decimal256::from_ratio(funds.0, 1u8) * market.withdrawal_fee;
-    let fee = Collateral(fee.to_uint_ceil());
-    let house = market.house.clone();
-    market
-        .add_liquidity(fee)
-        .assign_to(deps.storage, &mut market, &house, true)?;
-    let funds = funds.checked_sub(fee)?;
+    house_liquidity.credit_holder(deps.storage, &mut market, &house)?;
+
+    if let Some(min_collateral) = min_collateral {
+        if funds.0 < min_collateral.0 {
+            return Err(Error::WithdrawalSlippageExceeded {
+                expected: min_collateral,
+                actual: funds,
+            });
+        }
+    }
+
+    market.stats.withdrawal_volume += funds + fee;
+    market.stats.withdrawal_fees += fee;
+    market.stats.protocol_fees += protocol_fee;
+    market.stats.trades += 1;
+    market.stats.last_trade = Some(env.block.time);
+    market.snapshot_prices(deps.storage, env.block.time)?;
     MARKETS.save(deps.storage, id, &market)?;
     Ok(Response::new()
         .add_event(
@@ -389,88 +856,880 @@ fn withdraw(
                 .add_attribute("outcome-id", outcome.to_string())
                 .add_attribute("tokens", tokens.to_string())
                 .add_attribute("fee", fee.to_string())
+                .add_attribute("protocol-fee", protocol_fee.to_string())
                 .add_attribute("withdrawal", funds.to_string()),
         )
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
-            to_address: info.sender.into_string(),
-            amount: vec![Coin {
-                denom: market.denom,
-                amount: funds.0.try_into()?,
-            }],
-        })))
+        .add_messages(burn_msgs)
+        .add_message(market.denom.send(info.sender, funds)?))
 }
 
-fn set_winner(
-    deps: &mut DepsMut,
-    env: &Env,
-    info: MessageInfo,
-    id: MarketId,
+/// Remove outcome tokens from a wallet's internal balance.
+///
+/// Returns the updated holdings, which the caller is responsible for saving.
+fn debit_tokens(
+    store: &dyn Storage,
+    market: &mut StoredMarket,
+    sender: &Addr,
     outcome: OutcomeId,
-) -> Result<Response> {
-    let mut market = StoredMarket::load(deps.storage, id)?;
+    tokens: Token,
+) -> Result<ShareInfo> {
+    let id = market.id;
+    let mut share_info =
+        ShareInfo::load(store, market, sender)?.ok_or(Error::NoPositionsOnMarket { id })?;
 
-    if env.block.time < market.deposit_stop_date {
-        return Err(Error::MarketStillActive {
+    let user_tokens = share_info.get_outcome_mut(id, outcome)?;
+    if user_tokens.is_zero() {
+        return Err(Error::NoTokensFound { id, outcome });
+    }
+
+    if *user_tokens < tokens {
+        return Err(Error::InsufficientTokens {
             id,
-            now: env.block.time,
-            deposit_stop_date: market.deposit_stop_date,
+            outcome,
+            requested: tokens,
+            available: *user_tokens,
         });
     }
 
-    if info.sender != market.arbitrator {
-        return Err(Error::Unauthorized);
-    }
+    *user_tokens -= tokens;
 
-    if market.winner.is_some() {
-        return Err(Error::WinnerAlreadySet { id });
+    if share_info.get_outcome(market, outcome, false)?.is_zero() {
+        market.get_outcome_mut(outcome)?.wallets -= 1;
+        if !share_info.has_tokens() && share_info.shares.is_zero() {
+            market.total_wallets -= 1;
+        }
     }
 
-    market.winner = Some(outcome);
-    MARKETS.save(deps.storage, id, &market)?;
+    Ok(share_info)
+}
 
-    // Force a check that it's a valid outcome
-    market.get_outcome(outcome)?;
+fn mint_set(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    funds: Funds,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_deposits_open(env.block.time)?;
 
-    Ok(Response::new().add_event(
-        Event::new("set-winner")
-            .add_attribute("market-id", id.to_string())
-            .add_attribute("outcome-id", outcome.to_string()),
-    ))
-}
+    let amount = funds.require_funds(&market.denom)?;
+    let tokens = Token(amount.0);
 
-fn collect(deps: &mut DepsMut, info: MessageInfo, id: MarketId) -> Result<Response> {
-    let market = StoredMarket::load(deps.storage, id)?;
-    let winner = market.winner.ok_or(Error::NoWinnerSet { id })?;
+    let mut msgs = vec![];
+    if market.is_native() {
+        for idx in 0..market.outcomes.len() {
+            let outcome = market.outcomes[idx].id;
+            msgs.push(mint_outcome_tokens(
+                env,
+                &mut market,
+                outcome,
+                tokens,
+                &info.sender,
+            )?);
+        }
+        record_collateral(
+            deps.storage,
+            &market,
+            &info.sender,
+            amount,
+            Collateral::zero(),
+        )?;
+    } else {
+        let mut share_info = ShareInfo::load(deps.storage, &market, &info.sender)?
+            .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
+        let old_share_info = share_info.clone();
+        for outcome in &mut share_info.outcomes {
+            *outcome += tokens;
+        }
+        share_info.deposited += amount;
+        market.update_wallet_counts(&old_share_info, &share_info);
+        share_info.save(deps.storage, &market, &info.sender)?;
+    }
+    market.pool_size += amount;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("mint-set")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("tokens", tokens.to_string()),
+        )
+        .add_messages(msgs))
+}
+
+fn redeem_set(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    amount: Token,
+    funds: Funds,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_unresolved()?;
+
+    if amount.is_zero() {
+        return Err(Error::ZeroAmount);
+    }
+
+    let payout = Collateral(amount.0);
+    let burn_msgs = if market.is_native() {
+        let expected = vec![amount; market.outcomes.len()];
+        let (received, msgs) = burn_outcome_tokens(env, &market, funds)?;
+        if received != expected {
+            return Err(Error::IncorrectOutcomeTokens { expected, received });
+        }
+        market.return_minted(&received);
+        record_collateral(
+            deps.storage,
+            &market,
+            &info.sender,
+            Collateral::zero(),
+            payout,
+        )?;
+        msgs
+    } else {
+        funds.require_none()?;
+        let mut share_info = ShareInfo::load(deps.storage, &market, &info.sender)?
+            .ok_or(Error::NoPositionsOnMarket { id })?;
+        let old_share_info = share_info.clone();
+        for (outcome, tokens) in market.outcomes.iter().zip(share_info.outcomes.iter_mut()) {
+            if *tokens < amount {
+                return Err(Error::InsufficientTokens {
+                    id,
+                    outcome: outcome.id,
+                    requested: amount,
+                    available: *tokens,
+                });
+            }
+            *tokens -= amount;
+        }
+        share_info.withdrawn += payout;
+        market.update_wallet_counts(&old_share_info, &share_info);
+        share_info.save(deps.storage, &market, &info.sender)?;
+        vec![]
+    };
+    market.pool_size -= payout;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("redeem-set")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("tokens", amount.to_string()),
+        )
+        .add_messages(burn_msgs)
+        .add_message(market.denom.send(info.sender, payout)?))
+}
+
+fn transfer_tokens(
+    deps: &mut DepsMut,
+    info: MessageInfo,
+    id: MarketId,
+    outcome: OutcomeId,
+    amount: Token,
+    recipient: String,
+) -> Result<Response> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let event = move_tokens(deps, id, outcome, amount, &info.sender, &recipient)?;
+    Ok(Response::new().add_event(event))
+}
+
+/// Move outcome tokens between wallets, returning the transfer event.
+fn move_tokens(
+    deps: &mut DepsMut,
+    id: MarketId,
+    outcome: OutcomeId,
+    amount: Token,
+    sender: &Addr,
+    recipient: &Addr,
+) -> Result<Event> {
+    if amount.is_zero() {
+        return Err(Error::ZeroAmount);
+    }
+
+    transfer_position(deps, id, sender, recipient, |from, to| {
+        let available = *from.get_outcome_mut(id, outcome)?;
+        if available < amount {
+            return Err(Error::InsufficientTokens {
+                id,
+                outcome,
+                requested: amount,
+                available,
+            });
+        }
+        *from.get_outcome_mut(id, outcome)? -= amount;
+        *to.get_outcome_mut(id, outcome)? += amount;
+        Ok(())
+    })?;
+
+    Ok(Event::new("transfer-tokens")
+        .add_attribute("market-id", id.to_string())
+        .add_attribute("outcome-id", outcome.to_string())
+        .add_attribute("tokens", amount.to_string())
+        .add_attribute("sender", sender)
+        .add_attribute("recipient", recipient))
+}
+
+/// cw1155 transfer of outcome tokens.
+///
+/// Unlike [ExecuteMsg::TransferTokens], sending to yourself is allowed as cw1155
+/// expects. It runs the same checks but leaves the position untouched.
+fn send_tokens(
+    deps: &mut DepsMut,
+    id: MarketId,
+    outcome: OutcomeId,
+    amount: Token,
+    sender: &Addr,
+    recipient: &Addr,
+) -> Result<Event> {
+    if sender != recipient {
+        return move_tokens(deps, id, outcome, amount, sender, recipient);
+    }
+    if amount.is_zero() {
+        return Err(Error::ZeroAmount);
+    }
+
+    let market = StoredMarket::load(deps.storage, id)?;
+    market.assert_unresolved()?;
+    let available = ShareInfo::load(deps.storage, &market, sender)?
+        .ok_or(Error::NoPositionsOnMarket { id })?
+        .get_outcome(&market, outcome, false)?;
+    if available < amount {
+        return Err(Error::InsufficientTokens {
+            id,
+            outcome,
+            requested: amount,
+            available,
+        });
+    }
+
+    Ok(Event::new("transfer-tokens")
+        .add_attribute("market-id", id.to_string())
+        .add_attribute("outcome-id", outcome.to_string())
+        .add_attribute("tokens", amount.to_string())
+        .add_attribute("sender", sender)
+        .add_attribute("recipient", recipient))
+}
+
+fn transfer_shares(
+    deps: &mut DepsMut,
+    info: MessageInfo,
+    id: MarketId,
+    shares: LpShare,
+    recipient: String,
+) -> Result<Response> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let is_house = StoredMarket::load(deps.storage, id)?.house == info.sender;
+
+    transfer_position(deps, id, &info.sender, &recipient, |from, to| {
+        if shares.is_zero() || from.shares < shares {
+            return Err(Error::InsufficientShares {
+                id,
+                requested: shares,
+                available: from.shares,
+            });
+        }
+        if is_house && from.shares == shares {
+            return Err(Error::HouseMustRetainShares { id });
+        }
+        from.shares -= shares;
+        to.shares += shares;
+        Ok(())
+    })?;
+
+    Ok(Response::new().add_event(
+        Event::new("transfer-shares")
+            .add_attribute("market-id", id.to_string())
+            .add_attribute("shares", shares.to_string())
+            .add_attribute("sender", info.sender)
+            .add_attribute("recipient", recipient),
+    ))
+}
+
+/// Move part of a position between two wallets, keeping the wallet counts in sync.
+///
+/// The sender's cost basis moves along in proportion to the value transferred.
+fn transfer_position(
+    deps: &mut DepsMut,
+    id: MarketId,
+    sender: &Addr,
+    recipient: &Addr,
+    update: impl FnOnce(&mut ShareInfo, &mut ShareInfo) -> Result<()>,
+) -> Result<()> {
+    if sender == recipient {
+        return Err(Error::CannotTransferToSelf);
+    }
+
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_unresolved()?;
+
+    let mut from =
+        ShareInfo::load(deps.storage, &market, sender)?.ok_or(Error::NoPositionsOnMarket { id })?;
+    let mut to = ShareInfo::load(deps.storage, &market, recipient)?
+        .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
+    let old_from = from.clone();
+    let old_to = to.clone();
+
+    update(&mut from, &mut to)?;
+
+    // Cost basis follows the share of the position's value that moved
+    let prices = market.prices();
+    let before = old_from.payout(&market, &prices)?;
+    let after = from.payout(&market, &prices)?;
+    let moved = if !from.has_position() {
+        Decimal256::one()
+    } else if before.is_zero() {
+        Decimal256::zero()
+    } else {
+        Decimal256::from_ratio(before.0 - after.0, before.0)
+    };
+    let deposited = from.deposited * moved;
+    let withdrawn = from.withdrawn * moved;
+    from.deposited -= deposited;
+    from.withdrawn -= withdrawn;
+    to.deposited += deposited;
+    to.withdrawn += withdrawn;
+
+    market.update_wallet_counts(&old_from, &from);
+    market.update_wallet_counts(&old_to, &to);
+    from.save(deps.storage, &market, sender)?;
+    to.save(deps.storage, &market, recipient)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(())
+}
+
+fn propose_winner(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    outcome: Option<OutcomeId>,
+    scalar_result: Option<SignedDecimal256>,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+
+    if env.block.time < market.deposit_stop_date {
+        return Err(Error::MarketStillActive {
+            id,
+            now: env.block.time,
+            deposit_stop_date: market.deposit_stop_date,
+        });
+    }
+
+    if info.sender != market.arbitrator {
+        return Err(Error::Unauthorized);
+    }
+
+    let event = set_proposed_winner(&mut market, env.block.time, outcome, scalar_result)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new().add_event(event))
+}
+
+/// Propose a winner or scalar result, opening the market's dispute period.
+fn set_proposed_winner(
+    market: &mut StoredMarket,
+    now: Timestamp,
+    outcome: Option<OutcomeId>,
+    scalar_result: Option<SignedDecimal256>,
+) -> Result<Event> {
+    market.assert_unresolved()?;
+    market.assert_valid_result(outcome, scalar_result)?;
+
+    let dispute_ends = now.plus_seconds(market.dispute_period_seconds);
+    market.proposed_winner = Some(ProposedWinner {
+        outcome,
+        scalar_result,
+        dispute_ends,
+    });
+
+    let event = Event::new("propose-winner").add_attribute("market-id", market.id.to_string());
+    Ok(add_result(event, "", outcome, scalar_result)
+        .add_attribute("dispute-ends", dispute_ends.to_string()))
+}
+
+/// Add a market's winner or scalar result to an event.
+fn add_result(
+    event: Event,
+    prefix: &str,
+    outcome: Option<OutcomeId>,
+    scalar_result: Option<SignedDecimal256>,
+) -> Event {
+    match (outcome, scalar_result) {
+        (Some(outcome), _) => {
+            event.add_attribute(format!("{prefix}outcome-id"), outcome.to_string())
+        }
+        (None, Some(result)) => {
+            event.add_attribute(format!("{prefix}scalar-result"), result.to_string())
+        }
+        (None, None) => event,
+    }
+}
+
+fn resolve(deps: &mut DepsMut, env: &Env, id: MarketId) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    let StoredResolutionSource {
+        oracle,
+        feed_id,
+        strike,
+        comparator,
+        resolution_time,
+        max_publish_delay_seconds,
+        max_confidence_ratio,
+    } = market
+        .resolution
+        .clone()
+        .ok_or(Error::NoResolutionSource { id })?;
+
+    if env.block.time < resolution_time {
+        return Err(Error::ResolutionTimeNotReached {
+            id,
+            now: env.block.time,
+            resolution_time,
+        });
+    }
+    market.assert_unresolved()?;
+
+    let price = oracle::query_price(deps.querier, &oracle, &feed_id)?;
+    let end = resolution_time.plus_seconds(max_publish_delay_seconds);
+    let in_window = u64::try_from(price.publish_time).map_or(false, |time| {
+        (resolution_time.seconds()..=end.seconds()).contains(&time)
+    });
+    if !in_window {
+        return Err(Error::PriceOutsidePublishWindow {
+            publish_time: price.publish_time,
+            start: resolution_time,
+            end,
+        });
+    }
+    let value = price.to_decimal()?;
+    let ratio = price.confidence_ratio();
+    if ratio > max_confidence_ratio {
+        return Err(Error::PriceConfidenceTooWide {
+            ratio,
+            max: max_confidence_ratio,
+        });
+    }
+
+    let outcome = OutcomeId(if comparator.holds(value, strike) {
+        0
+    } else {
+        1
+    });
+    let event = set_proposed_winner(&mut market, env.block.time, Some(outcome), None)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("resolve")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("price", value.to_string())
+                .add_attribute("publish-time", price.publish_time.to_string())
+                .add_attribute("strike", strike.to_string()),
+        )
+        .add_event(event))
+}
+
+fn finalize_winner(deps: &mut DepsMut, env: &Env, id: MarketId) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+
+    let ProposedWinner {
+        outcome,
+        scalar_result,
+        dispute_ends,
+    } = market
+        .proposed_winner
+        .take()
+        .ok_or(Error::NoProposedWinner { id })?;
+    if env.block.time < dispute_ends {
+        return Err(Error::DisputePeriodActive {
+            id,
+            now: env.block.time,
+            dispute_ends,
+        });
+    }
+
+    market.winner = outcome;
+    market.scalar_result = scalar_result;
+    let res = Response::new().add_event(add_result(
+        Event::new("set-winner").add_attribute("market-id", id.to_string()),
+        "",
+        outcome,
+        scalar_result,
+    ));
+    let res = release_bond(deps.storage, &mut market, res, false)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(res)
+}
+
+fn override_winner(
+    deps: &mut DepsMut,
+    env: &Env,
+    id: MarketId,
+    outcome: Option<OutcomeId>,
+    scalar_result: Option<SignedDecimal256>,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_dispute_open(env.block.time)?;
+
+    let proposed = market
+        .proposed_winner
+        .take()
+        .ok_or(Error::NoProposedWinner { id })?;
+    market.assert_valid_result(outcome, scalar_result)?;
+
+    market.winner = outcome;
+    market.scalar_result = scalar_result;
+    let event = add_result(
+        Event::new("override-winner").add_attribute("market-id", id.to_string()),
+        "proposed-",
+        proposed.outcome,
+        proposed.scalar_result,
+    );
+    let res = Response::new().add_event(add_result(event, "", outcome, scalar_result));
+    let res = release_bond(deps.storage, &mut market, res, false)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(res)
+}
+
+fn void_market(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    invalid: bool,
+) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_dispute_open(env.block.time)?;
+
+    // Once a winner is proposed, only the admin can dispute it by voiding
+    let is_admin = info.sender == ADMIN.load(deps.storage)?;
+    let is_arbitrator =
+        info.sender == market.arbitrator && market.proposed_winner.is_none() && !invalid;
+    if !is_admin && !is_arbitrator {
+        return Err(Error::Unauthorized);
+    }
+
+    if market.has_result() {
+        return Err(Error::WinnerAlreadySet { id });
+    }
+
+    // The admin settles a bond held back by an arbitrator void
+    if is_admin && market.void_prices.is_some() && market.creator_bond.is_some() {
+        let res = release_bond(deps.storage, &mut market, Response::new(), invalid)?;
+        MARKETS.save(deps.storage, id, &market)?;
+        return Ok(res);
+    }
+    market.assert_not_voided()?;
+
+    market.proposed_winner = None;
+    let prices = market.prices();
+    let event = Event::new("void-market")
+        .add_attribute("market-id", id.to_string())
+        .add_attribute("prices", format!("{:?}", prices))
+        .add_attribute("invalid", invalid.to_string());
+    market.void_prices = Some(prices);
+    let mut res = Response::new().add_event(event);
+    // A permissionless creator can name themselves arbitrator, so only the
+    // admin decides whether the bond is returned or slashed
+    if is_admin {
+        res = release_bond(deps.storage, &mut market, res, invalid)?;
+    }
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(res)
+}
+
+/// Pay out a market's creator bond, if it still holds one.
+///
+/// The bond goes back to the creator, or to the admin when slashed.
+fn release_bond(
+    store: &dyn Storage,
+    market: &mut StoredMarket,
+    res: Response,
+    slash: bool,
+) -> Result<Response> {
+    let Some(CreatorBond { creator, amount }) = market.creator_bond.take() else {
+        return Ok(res);
+    };
+    let recipient = if slash { ADMIN.load(store)? } else { creator };
+    Ok(res
+        .add_event(
+            Event::new("creator-bond")
+                .add_attribute("market-id", market.id.to_string())
+                .add_attribute("recipient", recipient.to_string())
+                .add_attribute("amount", amount.to_string())
+                .add_attribute("slashed", slash.to_string()),
+        )
+        .add_message(market.denom.send(recipient, amount)?))
+}
+
+fn collect(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    id: MarketId,
+    funds: Funds,
+) -> Result<Response> {
+    let market = StoredMarket::load(deps.storage, id)?;
+    if !matches!(funds, Funds::NoFunds) {
+        return collect_native(deps, env, info, market, funds);
+    }
+    if let Some(prices) = &market.void_prices {
+        return collect_refund(deps, info, &market, prices);
+    }
+    let payouts = market.payouts()?;
     let mut share_info = ShareInfo::load(deps.storage, &market, &info.sender)?
         .ok_or(Error::NoPositionsOnMarket { id })?;
     if share_info.claimed_winnings {
         return Err(Error::AlreadyClaimedWinnings { id });
     }
     share_info.claimed_winnings = true;
-    let tokens = share_info.get_outcome(&market, winner, true)?;
-    if tokens.is_zero() {
-        return Err(Error::NoTokensFound {
-            id,
-            outcome: winner,
+    let winnings = share_info.payout(&market, &payouts)?;
+    if winnings.is_zero() {
+        return Err(match market.winner {
+            Some(outcome) => Error::NoTokensFound { id, outcome },
+            None => Error::NothingToCollect { id },
         });
     }
+    share_info.withdrawn += winnings;
     share_info.save(deps.storage, &market, &info.sender)?;
-    let winnings = Collateral(tokens.0);
+
+    let mut event = Event::new("collect").add_attribute("market-id", id.to_string());
+    if let Some(winner) = market.winner {
+        event = event
+            .add_attribute("winner", winner.to_string())
+            .add_attribute("tokens", winnings.to_string());
+    }
+    if let Some(result) = market.scalar_result {
+        event = event.add_attribute("scalar-result", result.to_string());
+    }
+    Ok(Response::new()
+        .add_event(event.add_attribute("winnings", winnings.to_string()))
+        .add_message(market.denom.send(info.sender, winnings)?))
+}
+
+/// Track collateral paid in and out by a wallet, for position valuation.
+fn record_collateral(
+    store: &mut dyn Storage,
+    market: &StoredMarket,
+    addr: &Addr,
+    deposited: Collateral,
+    withdrawn: Collateral,
+) -> Result<()> {
+    let mut share_info = ShareInfo::load(store, market, addr)?
+        .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
+    share_info.deposited += deposited;
+    share_info.withdrawn += withdrawn;
+    share_info.save(store, market, addr)?;
+    Ok(())
+}
+
+/// Redeem native outcome tokens attached to the message.
+fn collect_native(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    market: StoredMarket,
+    funds: Funds,
+) -> Result<Response> {
+    let id = market.id;
+    if !market.is_native() {
+        return Err(Error::NotNativeMarket { id });
+    }
+    let payouts = market.payouts()?;
+    // Like internal collection, pool accounting is left untouched once resolved.
+    let (received, burn_msgs) = burn_outcome_tokens(env, &market, funds)?;
+    let payout = received
+        .iter()
+        .zip(&payouts)
+        .fold(Collateral::zero(), |total, (tokens, payout)| {
+            total + Collateral(tokens.0) * *payout
+        });
+    if payout.is_zero() {
+        return Err(Error::NothingToCollect { id });
+    }
+    record_collateral(
+        deps.storage,
+        &market,
+        &info.sender,
+        Collateral::zero(),
+        payout,
+    )?;
 
     Ok(Response::new()
         .add_event(
-            Event::new("collect")
+            Event::new("collect-native")
                 .add_attribute("market-id", id.to_string())
-                .add_attribute("winner", winner.to_string())
-                .add_attribute("tokens", tokens.to_string()),
+                .add_attribute("tokens", format!("{:?}", received))
+                .add_attribute("payout", payout.to_string()),
         )
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
-            to_address: info.sender.into_string(),
-            amount: vec![Coin {
-                denom: market.denom,
-                amount: winnings.0.try_into()?,
-            }],
-        })))
+        .add_messages(burn_msgs)
+        .add_message(market.denom.send(info.sender, payout)?))
+}
+
+/// Mint native outcome tokens to a wallet.
+fn mint_outcome_tokens(
+    env: &Env,
+    market: &mut StoredMarket,
+    outcome: OutcomeId,
+    tokens: Token,
+    recipient: &Addr,
+) -> Result<CosmosMsg> {
+    let outcome = market.get_outcome_mut(outcome)?;
+    let denom = outcome
+        .native_denom
+        .as_deref()
+        .expect("Native markets have a denom for each outcome");
+    outcome.minted += tokens;
+    Ok(tokenfactory::mint(
+        &env.contract.address,
+        denom,
+        tokens,
+        recipient,
+    ))
+}
+
+/// Burn the native outcome tokens attached to a message.
+///
+/// Returns the tokens received for each outcome.
+fn burn_outcome_tokens(
+    env: &Env,
+    market: &StoredMarket,
+    funds: Funds,
+) -> Result<(Vec<Token>, Vec<CosmosMsg>)> {
+    let received = funds.require_outcome_tokens(market)?;
+    let mut msgs = vec![];
+    for (outcome, tokens) in market.outcomes.iter().zip(&received) {
+        if let (Some(denom), false) = (&outcome.native_denom, tokens.is_zero()) {
+            msgs.push(tokenfactory::burn(&env.contract.address, denom, *tokens));
+        }
+    }
+    Ok((received, msgs))
+}
+
+fn collect_refund(
+    deps: &mut DepsMut,
+    info: MessageInfo,
+    market: &StoredMarket,
+    prices: &[Decimal256],
+) -> Result<Response> {
+    let id = market.id;
+    let mut share_info = ShareInfo::load(deps.storage, market, &info.sender)?
+        .ok_or(Error::NoPositionsOnMarket { id })?;
+    if share_info.claimed_winnings {
+        return Err(Error::AlreadyClaimedWinnings { id });
+    }
+    share_info.claimed_winnings = true;
+    let refund = share_info.payout(market, prices)?;
+    if refund.is_zero() {
+        return Err(Error::NothingToCollect { id });
+    }
+    share_info.withdrawn += refund;
+    share_info.save(deps.storage, market, &info.sender)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("collect-refund")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("refund", refund.to_string()),
+        )
+        .add_message(market.denom.send(info.sender, refund)?))
+}
+
+fn update_config(deps: &mut DepsMut, config: Config) -> Result<Response> {
+    let config = config.validate(deps.api)?;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_event(Event::new("update-config")))
+}
+
+fn claim_protocol_fees(deps: &mut DepsMut, id: MarketId) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    let treasury = CONFIG
+        .load(deps.storage)?
+        .treasury
+        .ok_or(Error::NoTreasury)?;
+    let amount = std::mem::replace(&mut market.protocol_fees, Collateral::zero());
+    if amount.is_zero() {
+        return Err(Error::NothingToCollect { id });
+    }
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("claim-protocol-fees")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("treasury", &treasury)
+                .add_attribute("amount", amount.to_string()),
+        )
+        .add_message(market.denom.send(treasury, amount)?))
+}
+
+fn backfill_wallet_markets(deps: &mut DepsMut, limit: u32) -> Result<Response> {
+    let last = match WALLET_MARKETS_BACKFILL.load(deps.storage)? {
+        WalletMarketsBackfill::InProgress { last } => last,
+        WalletMarketsBackfill::Done => return Err(Error::WalletMarketsBackfilled),
+    };
+    let limit = usize::try_from(limit)?;
+    let holders = HOLDERS
+        .range(
+            deps.storage,
+            last.as_ref()
+                .map(|(id, addr)| Bound::exclusive((*id, addr))),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for ((id, addr), share_info) in &holders {
+        if share_info.has_position() {
+            WALLET_MARKETS.save(deps.storage, (addr, *id), &())?;
+        }
+    }
+    let status = if holders.len() < limit {
+        WalletMarketsBackfill::Done
+    } else {
+        WalletMarketsBackfill::InProgress {
+            last: holders.last().map(|(key, _)| key.clone()).or(last),
+        }
+    };
+    let done = matches!(status, WalletMarketsBackfill::Done);
+    WALLET_MARKETS_BACKFILL.save(deps.storage, &status)?;
+
+    Ok(Response::new().add_event(
+        Event::new("backfill-wallet-markets")
+            .add_attribute("holders", holders.len().to_string())
+            .add_attribute("done", done.to_string()),
+    ))
+}
+
+fn claim_referral_fees(deps: &mut DepsMut, info: MessageInfo, id: MarketId) -> Result<Response> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    let mut fees = REFERRAL_FEES
+        .may_load(deps.storage, (&info.sender, id))?
+        .unwrap_or_default();
+    let amount = std::mem::replace(&mut fees.unclaimed, Collateral::zero());
+    if amount.is_zero() {
+        return Err(Error::NothingToCollect { id });
+    }
+    market.referral_fees -= amount;
+    REFERRAL_FEES.save(deps.storage, (&info.sender, id), &fees)?;
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_event(
+            Event::new("claim-referral-fees")
+                .add_attribute("market-id", id.to_string())
+                .add_attribute("referrer", info.sender.to_string())
+                .add_attribute("amount", amount.to_string()),
+        )
+        .add_message(market.denom.send(info.sender, amount)?))
 }
 
 fn appoint_admin(deps: &mut DepsMut, addr: String) -> Result<Response> {
@@ -491,3 +1750,121 @@ fn accept_admin(deps: &mut DepsMut, info: MessageInfo) -> Result<Response> {
     ADMIN.save(deps.storage, &appointed)?;
     Ok(Response::new().add_event(Event::new("accept-admin").add_attribute("new-admin", appointed)))
 }
+
+/// Check that the sender may move the owner's tokens through the cw1155 interface.
+fn assert_can_send(store: &dyn Storage, env: &Env, owner: &Addr, sender: &Addr) -> Result<()> {
+    if owner == sender {
+        return Ok(());
+    }
+    match APPROVALS.may_load(store, (owner, sender))? {
+        Some(expires) if !expires.is_expired(&env.block) => Ok(()),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_from(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    from: String,
+    to: String,
+    token_id: String,
+    value: Uint128,
+    msg: Option<Binary>,
+) -> Result<Response> {
+    let from = deps.api.addr_validate(&from)?;
+    let to = deps.api.addr_validate(&to)?;
+    assert_can_send(deps.storage, env, &from, &info.sender)?;
+
+    let OutcomeToken { market, outcome } = token_id.parse()?;
+    let event = send_tokens(deps, market, outcome, Token(value.into()), &from, &to)?;
+
+    let mut res = Response::new().add_event(event);
+    if let Some(msg) = msg {
+        res = res.add_message(
+            Cw1155ReceiverMsg::Receive(Cw1155ReceiveMsg {
+                operator: info.sender.into_string(),
+                from: Some(from.into_string()),
+                token_id,
+                amount: value,
+                msg,
+            })
+            .into_cosmos_msg(to.into_string())?,
+        );
+    }
+    Ok(res)
+}
+
+fn batch_send_from(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    from: String,
+    to: String,
+    batch: Vec<(String, Uint128)>,
+    msg: Option<Binary>,
+) -> Result<Response> {
+    let from = deps.api.addr_validate(&from)?;
+    let to = deps.api.addr_validate(&to)?;
+    assert_can_send(deps.storage, env, &from, &info.sender)?;
+
+    let mut res = Response::new();
+    for (token_id, value) in &batch {
+        let OutcomeToken { market, outcome } = token_id.parse()?;
+        res = res.add_event(send_tokens(
+            deps,
+            market,
+            outcome,
+            Token((*value).into()),
+            &from,
+            &to,
+        )?);
+    }
+
+    if let Some(msg) = msg {
+        res = res.add_message(
+            Cw1155ReceiverMsg::BatchReceive(Cw1155BatchReceiveMsg {
+                operator: info.sender.into_string(),
+                from: Some(from.into_string()),
+                batch,
+                msg,
+            })
+            .into_cosmos_msg(to.into_string())?,
+        );
+    }
+    Ok(res)
+}
+
+fn approve_all(
+    deps: &mut DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    operator: String,
+    expires: Option<Expiration>,
+) -> Result<Response> {
+    let operator = deps.api.addr_validate(&operator)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(Error::ApprovalExpired);
+    }
+    APPROVALS.save(deps.storage, (&info.sender, &operator), &expires)?;
+
+    Ok(Response::new().add_event(
+        Event::new("approve-all")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator)
+            .add_attribute("expires", expires.to_string()),
+    ))
+}
+
+fn revoke_all(deps: &mut DepsMut, info: MessageInfo, operator: String) -> Result<Response> {
+    let operator = deps.api.addr_validate(&operator)?;
+    APPROVALS.remove(deps.storage, (&info.sender, &operator));
+
+    Ok(Response::new().add_event(
+        Event::new("revoke-all")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator),
+    ))
+}
@@ -5,10 +5,13 @@ pub fn instantiate(
     deps: DepsMut,
     env: Env,
     _info: MessageInfo,
-    InstantiateMsg { admin }: InstantiateMsg,
+    InstantiateMsg { admin, config }: InstantiateMsg,
 ) -> Result<Response> {
     let admin = deps.api.addr_validate(&admin)?;
     ADMIN.save(deps.storage, &admin)?;
+    let config = config.validate(deps.api)?;
+    CONFIG.save(deps.storage, &config)?;
+    WALLET_MARKETS_BACKFILL.save(deps.storage, &WalletMarketsBackfill::Done)?;
 
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
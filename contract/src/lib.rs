@@ -7,6 +7,7 @@ mod error;
 mod execute;
 mod instantiate;
 mod migrate;
+mod oracle;
 mod prelude;
 mod query;
 #[cfg(debug_assertions)]
@@ -14,6 +15,7 @@ mod sanity;
 mod state;
 #[cfg(test)]
 mod tests;
+mod tokenfactory;
 mod types;
 mod util;
 
@@ -30,6 +30,20 @@ pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response> {
 
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    if !CONFIG.exists(deps.storage) {
+        CONFIG.save(deps.storage, &Config::default())?;
+    }
+
+    // Holders created before the wallet index existed are added in batches
+    // with ExecuteMsg::BackfillWalletMarkets, since there may be too many for
+    // a single migration.
+    if !WALLET_MARKETS_BACKFILL.exists(deps.storage) {
+        WALLET_MARKETS_BACKFILL.save(
+            deps.storage,
+            &WalletMarketsBackfill::InProgress { last: None },
+        )?;
+    }
+
     sanity(deps.storage, &env);
 
     Ok(Response::default())
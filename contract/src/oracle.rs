@@ -0,0 +1,85 @@
+//! Minimal Pyth-compatible oracle interface for resolving price markets.
+//!
+//! Only the fields needed for resolution are modeled. The layout follows the
+//! Pyth CosmWasm contract's `price_feed` query, so any oracle answering that
+//! query can be used as a resolution source.
+
+use cosmwasm_std::{Int64, QuerierWrapper, Uint64};
+
+use crate::prelude::*;
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OracleQueryMsg {
+    PriceFeed { id: String },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct PriceFeedResponse {
+    pub price_feed: PriceFeed,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct PriceFeed {
+    pub id: String,
+    pub price: Price,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct Price {
+    /// Price in units of `10^expo`
+    pub price: Int64,
+    /// Confidence interval around the price, in the same units
+    pub conf: Uint64,
+    pub expo: i32,
+    /// Unix timestamp in seconds
+    pub publish_time: i64,
+}
+
+impl Price {
+    /// The price as a decimal, failing for non-positive prices.
+    pub(crate) fn to_decimal(&self) -> Result<Decimal256> {
+        let price = u128::try_from(self.price.i64())
+            .ok()
+            .filter(|price| *price > 0)
+            .ok_or(Error::InvalidOraclePrice {
+                price: self.price.i64(),
+            })?;
+        Ok(match u32::try_from(self.expo) {
+            Ok(expo) => Decimal256::from_ratio(price, 1u8)
+                .checked_mul(Decimal256::from_ratio(10u8, 1u8).checked_pow(expo)?)?,
+            Err(_) => Decimal256::from_atomics(price, self.expo.unsigned_abs())?,
+        })
+    }
+
+    /// Ratio of the confidence interval to the price.
+    pub(crate) fn confidence_ratio(&self) -> Decimal256 {
+        Decimal256::from_ratio(self.conf.u64(), self.price.i64().unsigned_abs())
+    }
+}
+
+/// Query the latest price of a feed.
+pub(crate) fn query_price(querier: QuerierWrapper, oracle: &Addr, feed_id: &str) -> Result<Price> {
+    let PriceFeedResponse { price_feed } = querier.query_wasm_smart(
+        oracle,
+        &OracleQueryMsg::PriceFeed {
+            id: feed_id.to_owned(),
+        },
+    )?;
+    Ok(price_feed.price)
+}
+
+impl Comparator {
+    /// Does the observed price satisfy the comparison against the strike?
+    pub(crate) fn holds(self, price: Decimal256, strike: Decimal256) -> bool {
+        match self {
+            Comparator::Above => price > strike,
+            Comparator::AtOrAbove => price >= strike,
+            Comparator::Below => price < strike,
+            Comparator::AtOrBelow => price <= strike,
+        }
+    }
+}
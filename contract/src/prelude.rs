@@ -1,7 +1,7 @@
 pub use crate::{api::*, constants::*, error::*, state::*, types::*};
 pub use cosmwasm_std::{
     entry_point, Addr, Api, Binary, Coin, Decimal256, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult, Storage, Timestamp, Uint128,
+    SignedDecimal256, StdError, StdResult, Storage, Timestamp, Uint128,
 };
 pub use cw_storage_plus::{Item, Map};
 pub use schemars::JsonSchema;
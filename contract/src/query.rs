@@ -1,14 +1,88 @@
-use cosmwasm_std::to_json_binary;
+use cosmwasm_std::{to_json_binary, Order};
+use cw_storage_plus::Bound;
 
-use crate::prelude::*;
+use crate::{
+    cpmm::{Deposit, Withdrawal},
+    prelude::*,
+};
 
 #[entry_point]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary> {
     sanity(deps.storage, &env);
     match msg {
         QueryMsg::GlobalInfo {} => to_json_binary(&global_info(deps)?),
+        QueryMsg::Config {} => to_json_binary(&CONFIG.load(deps.storage)?),
         QueryMsg::Market { id } => to_json_binary(&market(deps, id)?),
+        QueryMsg::Markets {
+            start_after,
+            limit,
+            status,
+        } => to_json_binary(&markets(deps, &env, start_after, limit, status)?),
         QueryMsg::Positions { id, addr } => to_json_binary(&positions(deps, id, addr)?),
+        QueryMsg::PositionValue { id, addr } => {
+            to_json_binary(&position_value(deps, &env, id, addr)?)
+        }
+        QueryMsg::Holders {
+            id,
+            outcome,
+            start_after,
+            limit,
+        } => to_json_binary(&holders(deps, id, outcome, start_after, limit)?),
+        QueryMsg::WalletPositions {
+            addr,
+            start_after,
+            limit,
+        } => to_json_binary(&wallet_positions(deps, &env, addr, start_after, limit)?),
+        QueryMsg::ReferralFees {
+            referrer,
+            start_after,
+            limit,
+        } => to_json_binary(&referral_fees(deps, referrer, start_after, limit)?),
+        QueryMsg::Prices { id } => to_json_binary(&prices(deps, id)?),
+        QueryMsg::PriceHistory {
+            id,
+            start_after,
+            limit,
+        } => to_json_binary(&price_history(deps, id, start_after, limit)?),
+        QueryMsg::SimulateDeposit {
+            id,
+            outcome,
+            amount,
+            liquidity,
+            referred,
+        } => to_json_binary(&simulate_deposit(
+            deps, &env, id, outcome, amount, liquidity, referred,
+        )?),
+        QueryMsg::SimulateWithdraw {
+            id,
+            outcome,
+            tokens,
+        } => to_json_binary(&simulate_withdraw(deps, &env, id, outcome, tokens)?),
+        QueryMsg::Balance { owner, token_id } => to_json_binary(&BalanceResp {
+            balance: balance(deps, &owner, &token_id)?,
+        }),
+        QueryMsg::BatchBalance { owner, token_ids } => to_json_binary(&BatchBalanceResp {
+            balances: token_ids
+                .iter()
+                .map(|token_id| balance(deps, &owner, token_id))
+                .collect::<Result<_>>()?,
+        }),
+        QueryMsg::ApprovedForAll {
+            owner,
+            include_expired,
+            start_after,
+            limit,
+        } => to_json_binary(&approved_for_all(
+            deps,
+            &env,
+            owner,
+            include_expired.unwrap_or_default(),
+            start_after,
+            limit,
+        )?),
+        QueryMsg::IsApprovedForAll { owner, operator } => {
+            to_json_binary(&is_approved_for_all(deps, &env, owner, operator)?)
+        }
     }
     .map_err(Error::from)
 }
@@ -24,9 +98,424 @@ fn market(deps: Deps, id: MarketId) -> Result<MarketResp> {
     StoredMarket::load(deps.storage, id)
 }
 
+fn markets(
+    deps: Deps,
+    env: &Env,
+    start_after: Option<MarketId>,
+    limit: Option<u32>,
+    status: Option<MarketStatus>,
+) -> Result<MarketsResp> {
+    let limit: usize = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let mut markets = vec![];
+    let mut last_scanned = None;
+    let mut next_start_after = None;
+    for (scanned, res) in MARKETS
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .enumerate()
+    {
+        // Stop once the page is full, or a rare status has scanned too many markets
+        if markets.len() == limit || scanned == MAX_MARKETS_SCANNED {
+            next_start_after = last_scanned;
+            break;
+        }
+        let (id, market) = res?;
+        last_scanned = Some(id);
+        let market_status = market.status(env.block.time);
+        if status.map_or(false, |status| status != market_status) {
+            continue;
+        }
+        markets.push(MarketSummary {
+            id: market.id,
+            title: market.title,
+            status: market_status,
+            outcomes: market.outcomes.into_iter().map(|o| o.label).collect(),
+            denom: market.denom,
+            pool_size: market.pool_size,
+            deposit_stop_date: market.deposit_stop_date,
+            withdrawal_stop_date: market.withdrawal_stop_date,
+            winner: market.winner,
+            scalar_result: market.scalar_result,
+            total_wallets: market.total_wallets,
+        });
+    }
+    Ok(MarketsResp {
+        markets,
+        next_start_after,
+    })
+}
+
 fn positions(deps: Deps, id: MarketId, addr: String) -> Result<PositionsResp> {
     let addr = deps.api.addr_validate(&addr)?;
     let market = StoredMarket::load(deps.storage, id)?;
     Ok(ShareInfo::load(deps.storage, &market, &addr)?
         .unwrap_or_else(|| ShareInfo::new(market.outcomes.len())))
 }
+
+fn position_value(deps: Deps, env: &Env, id: MarketId, addr: String) -> Result<PositionValueResp> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let market = StoredMarket::load(deps.storage, id)?;
+    let share_info = ShareInfo::load(deps.storage, &market, &addr)?
+        .unwrap_or_else(|| ShareInfo::new(market.outcomes.len()));
+
+    // Claimed winnings have already been paid out
+    let (value, payout_if_wins) = if share_info.claimed_winnings {
+        (
+            Collateral::zero(),
+            vec![Collateral::zero(); market.outcomes.len()],
+        )
+    } else {
+        let payout_if_wins = market
+            .outcomes
+            .iter()
+            .map(|outcome| {
+                share_info
+                    .get_outcome(&market, outcome.id, true)
+                    .map(|tokens| Collateral(tokens.0))
+            })
+            .collect::<Result<_>>()?;
+        let value = match market.payouts() {
+            Ok(payouts) => share_info.payout(&market, &payouts)?,
+            Err(_) => mark_to_market(market, &share_info, env.block.time)?,
+        };
+        (value, payout_if_wins)
+    };
+
+    Ok(PositionValueResp {
+        cost_basis: share_info.deposited,
+        withdrawn: share_info.withdrawn,
+        value,
+        realized_pnl: share_info.withdrawn.signed_sub(share_info.deposited)?,
+        total_pnl: (share_info.withdrawn + value).signed_sub(share_info.deposited)?,
+        payout_if_wins,
+    })
+}
+
+/// Value of selling every token held, plus the share of pool tokens at current prices.
+fn mark_to_market(
+    mut market: StoredMarket,
+    share_info: &ShareInfo,
+    now: Timestamp,
+) -> Result<Collateral> {
+    let pool_share = ShareInfo {
+        outcomes: vec![Token::zero(); market.outcomes.len()],
+        ..share_info.clone()
+    };
+    let mut value = pool_share.payout(&market, &market.prices())?;
+    for (idx, tokens) in share_info.outcomes.iter().enumerate() {
+        if !tokens.is_zero() {
+            let outcome = market.outcomes[idx].id;
+            value += market.withdraw(now, outcome, *tokens)?.funds;
+        }
+    }
+    Ok(value)
+}
+
+fn holders(
+    deps: Deps,
+    id: MarketId,
+    outcome: Option<OutcomeId>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<HoldersResp> {
+    let market = StoredMarket::load(deps.storage, id)?;
+    if let Some(outcome) = outcome {
+        market.get_outcome(outcome)?;
+    }
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let holders = HOLDERS
+        .prefix(id)
+        .range(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .filter(|res| match res {
+            Ok((_, share_info)) => match outcome {
+                Some(outcome) => !share_info.outcomes[outcome.usize()].is_zero(),
+                None => share_info.has_position(),
+            },
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|res| {
+            res.map(|(addr, share_info)| Holder {
+                addr,
+                outcomes: share_info.outcomes,
+                shares: share_info.shares,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(HoldersResp { holders })
+}
+
+fn referral_fees(
+    deps: Deps,
+    referrer: String,
+    start_after: Option<MarketId>,
+    limit: Option<u32>,
+) -> Result<ReferralFeesResp> {
+    let referrer = deps.api.addr_validate(&referrer)?;
+    let limit = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let markets = REFERRAL_FEES
+        .prefix(&referrer)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|res| {
+            let (id, fees) = res?;
+            let market = StoredMarket::load(deps.storage, id)?;
+            Ok(MarketReferralFees {
+                id,
+                denom: market.denom,
+                unclaimed: fees.unclaimed,
+                total: fees.total,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(ReferralFeesResp { markets })
+}
+
+fn wallet_positions(
+    deps: Deps,
+    env: &Env,
+    addr: String,
+    start_after: Option<MarketId>,
+    limit: Option<u32>,
+) -> Result<WalletPositionsResp> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let limit = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let positions = WALLET_MARKETS
+        .prefix(&addr)
+        .keys(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|id| {
+            let market = StoredMarket::load(deps.storage, id?)?;
+            let share_info = ShareInfo::load(deps.storage, &market, &addr)?
+                .expect("Indexed wallets have a holder record");
+            let final_payouts = market.payouts().ok();
+            // Claimed winnings have already been paid out
+            let value = if share_info.claimed_winnings {
+                Collateral::zero()
+            } else {
+                share_info.payout(
+                    &market,
+                    final_payouts.as_deref().unwrap_or(&market.prices()),
+                )?
+            };
+            Ok(WalletPosition {
+                id: market.id,
+                status: market.status(env.block.time),
+                outcomes: share_info.outcomes,
+                shares: share_info.shares,
+                value,
+                claimable: final_payouts.is_some() && !value.is_zero(),
+                claimed: share_info.claimed_winnings,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(WalletPositionsResp { positions })
+}
+
+fn prices(deps: Deps, id: MarketId) -> Result<PricesResp> {
+    let market = StoredMarket::load(deps.storage, id)?;
+    Ok(PricesResp {
+        outcomes: market
+            .prices()
+            .into_iter()
+            .zip(market.outcomes.iter())
+            .map(|(price, outcome)| OutcomePrice {
+                id: outcome.id,
+                price,
+                pool_tokens: outcome.pool_tokens,
+            })
+            .collect(),
+        pool_size: market.pool_size,
+    })
+}
+
+fn price_history(
+    deps: Deps,
+    id: MarketId,
+    start_after: Option<Timestamp>,
+    limit: Option<u32>,
+) -> Result<PriceHistoryResp> {
+    StoredMarket::load(deps.storage, id)?;
+    let limit = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let start_after = start_after.map(|timestamp| Bound::exclusive(price_bucket(timestamp)));
+    let snapshots = PRICE_HISTORY
+        .prefix(id)
+        .range(deps.storage, start_after, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|(_, snapshot)| snapshot))
+        .collect::<StdResult<_>>()?;
+    Ok(PriceHistoryResp { snapshots })
+}
+
+fn simulate_deposit(
+    deps: Deps,
+    env: &Env,
+    id: MarketId,
+    outcome: OutcomeId,
+    amount: Collateral,
+    liquidity: Decimal256,
+    referred: bool,
+) -> Result<SimulateDepositResp> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_deposits_open(env.block.time)?;
+
+    let Deposit {
+        fee,
+        protocol_fee,
+        referral_fee: _,
+        house_liquidity,
+        lp,
+        tokens,
+    } = market.deposit(env.block.time, outcome, amount, liquidity, referred)?;
+
+    Ok(SimulateDepositResp {
+        tokens,
+        fee,
+        protocol_fee,
+        fee_rate: market.deposit_fee_at(env.block.time),
+        lp_shares: lp,
+        returned_to_house: house_liquidity.returned_to_user,
+        effective_price: amount / Collateral(tokens.0),
+        probabilities: market.prices(),
+    })
+}
+
+fn simulate_withdraw(
+    deps: Deps,
+    env: &Env,
+    id: MarketId,
+    outcome: OutcomeId,
+    tokens: Token,
+) -> Result<SimulateWithdrawResp> {
+    let mut market = StoredMarket::load(deps.storage, id)?;
+    market.assert_withdrawals_open(env.block.time)?;
+
+    let Withdrawal {
+        funds,
+        fee,
+        protocol_fee,
+        returned,
+        house_liquidity,
+    } = market.withdraw(env.block.time, outcome, tokens)?;
+
+    Ok(SimulateWithdrawResp {
+        funds,
+        fee,
+        protocol_fee,
+        fee_rate: market.withdrawal_fee_at(env.block.time),
+        house_lp_shares: house_liquidity.lp,
+        returned_to_house: returned
+            .into_iter()
+            .zip(house_liquidity.returned_to_user)
+            .map(|(dust, from_fee)| dust + from_fee)
+            .collect(),
+        effective_price: if tokens.is_zero() {
+            Decimal256::zero()
+        } else {
+            funds / Collateral(tokens.0)
+        },
+        probabilities: market.prices(),
+    })
+}
+
+fn balance(deps: Deps, owner: &str, token_id: &str) -> Result<Uint128> {
+    let owner = deps.api.addr_validate(owner)?;
+    let OutcomeToken { market, outcome } = token_id.parse()?;
+    let market = StoredMarket::load(deps.storage, market)?;
+    market.get_outcome(outcome)?;
+    let tokens = match ShareInfo::load(deps.storage, &market, &owner)? {
+        Some(share_info) => share_info.outcomes[outcome.usize()],
+        None => Token::zero(),
+    };
+    Ok(tokens.0.try_into()?)
+}
+
+fn approved_for_all(
+    deps: Deps,
+    env: &Env,
+    owner: String,
+    include_expired: bool,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<ApprovedForAllResp> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT)
+        .try_into()?;
+    let operators = APPROVALS
+        .prefix(&owner)
+        .range(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .filter(|res| match res {
+            Ok((_, expires)) => include_expired || !expires.is_expired(&env.block),
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|res| {
+            res.map(|(spender, expires)| Approval {
+                spender: spender.into_string(),
+                expires,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ApprovedForAllResp { operators })
+}
+
+fn is_approved_for_all(
+    deps: Deps,
+    env: &Env,
+    owner: String,
+    operator: String,
+) -> Result<IsApprovedForAllResp> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let operator = deps.api.addr_validate(&operator)?;
+    let approved = APPROVALS
+        .may_load(deps.storage, (&owner, &operator))?
+        .map_or(false, |expires| !expires.is_expired(&env.block));
+    Ok(IsApprovedForAllResp { approved })
+}
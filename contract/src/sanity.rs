@@ -1,6 +1,21 @@
 use crate::prelude::*;
 
 pub fn sanity(store: &dyn Storage, env: &Env) {
+    let mut referral_fees = std::collections::BTreeMap::<u32, ReferralFees>::new();
+    for res in REFERRAL_FEES.range(store, None, None, cosmwasm_std::Order::Ascending) {
+        let ((_, market_id), fees) = res.unwrap();
+        assert!(fees.unclaimed.0 <= fees.total.0);
+        assert!(!fees.total.is_zero());
+        let computed = referral_fees.entry(market_id.0).or_default();
+        computed.unclaimed += fees.unclaimed;
+        computed.total += fees.total;
+    }
+
+    let backfilled = !matches!(
+        WALLET_MARKETS_BACKFILL.may_load(store).unwrap(),
+        Some(WalletMarketsBackfill::InProgress { .. })
+    );
+
     for market in MARKETS.range(store, None, None, cosmwasm_std::Order::Ascending) {
         let (
             market_id,
@@ -13,21 +28,75 @@ pub fn sanity(store: &dyn Storage, env: &Env) {
                 denom: _,
                 deposit_fee: _,
                 withdrawal_fee: _,
+                fee_schedule,
+                resolution,
+                scalar,
                 pool_size,
                 deposit_stop_date,
                 withdrawal_stop_date,
                 winner,
+                scalar_result,
+                dispute_period_seconds: _,
+                proposed_winner,
+                void_prices,
                 house,
                 total_wallets,
                 lp_shares,
                 lp_wallets,
+                stats,
+                creator_bond: _,
+                protocol_fee_share,
+                protocol_fees,
+                referral_fee_share,
+                referral_fees: unclaimed_referral_fees,
             },
         ) = market.unwrap();
 
         // Basic sanity of config values
         assert_eq!(market_id, id);
         assert!(deposit_stop_date >= withdrawal_stop_date);
-        assert!(winner.is_none() || deposit_stop_date <= env.block.time);
+        assert!(fee_schedule.len() <= MAX_FEE_SCHEDULE_POINTS);
+        if let Some(resolution) = &resolution {
+            assert_eq!(market_outcomes.len(), 2);
+            assert!(resolution.resolution_time >= deposit_stop_date);
+        }
+        assert!(fee_schedule
+            .windows(2)
+            .all(|window| window[0].seconds_remaining > window[1].seconds_remaining));
+        assert!(protocol_fee_share <= Decimal256::one());
+        assert!(protocol_fee_share > Decimal256::zero() || stats.protocol_fees.is_zero());
+        assert!(protocol_fees.0 <= stats.protocol_fees.0);
+        assert!(protocol_fee_share + referral_fee_share <= Decimal256::one());
+        assert!(referral_fee_share > Decimal256::zero() || stats.referral_fees.is_zero());
+        assert!(
+            stats.protocol_fees.0 + stats.referral_fees.0
+                <= stats.deposit_fees.0 + stats.withdrawal_fees.0
+        );
+        let computed_referral_fees = referral_fees.remove(&market_id.0).unwrap_or_default();
+        assert_eq!(computed_referral_fees.unclaimed, unclaimed_referral_fees);
+        assert_eq!(computed_referral_fees.total, stats.referral_fees);
+        let has_result = winner.is_some() || scalar_result.is_some();
+        assert!(winner.is_none() || scalar_result.is_none());
+        assert!(winner.is_none() || scalar.is_none());
+        assert!(scalar_result.is_none() || scalar.is_some());
+        if let Some(ScalarRange { min, max }) = &scalar {
+            assert_eq!(market_outcomes.len(), 2);
+            assert!(min < max);
+            assert!(resolution.is_none());
+        }
+        if let Some(proposed) = &proposed_winner {
+            assert_eq!(proposed.outcome.is_some(), scalar.is_none());
+            assert_eq!(proposed.scalar_result.is_some(), scalar.is_some());
+        }
+        assert!(!has_result || deposit_stop_date <= env.block.time);
+        assert!(!has_result || void_prices.is_none());
+        assert!(proposed_winner.is_none() || !has_result);
+        assert!(proposed_winner.is_none() || void_prices.is_none());
+        assert!(proposed_winner.is_none() || deposit_stop_date <= env.block.time);
+        if let Some(prices) = &void_prices {
+            assert_eq!(prices.len(), market_outcomes.len());
+            assert!(prices.iter().sum::<Decimal256>() <= Decimal256::one());
+        }
 
         // We always need an entry for the house
         let house = HOLDERS.load(store, (market_id, &house)).unwrap();
@@ -38,7 +107,7 @@ pub fn sanity(store: &dyn Storage, env: &Env) {
             .enumerate()
             .map(|(id, outcome)| {
                 assert_eq!(id, outcome.id.usize());
-                outcome.pool_tokens
+                outcome.pool_tokens + outcome.minted
             })
             .collect::<Vec<_>>();
         let mut computed_shares = LpShare::zero();
@@ -53,13 +122,18 @@ pub fn sanity(store: &dyn Storage, env: &Env) {
                 .prefix(market_id)
                 .range(store, None, None, cosmwasm_std::Order::Ascending)
         {
-            let ShareInfo {
-                outcomes,
-                shares,
-                claimed_winnings,
-            } = holder.unwrap().1;
+            let (
+                addr,
+                ShareInfo {
+                    outcomes,
+                    shares,
+                    claimed_winnings,
+                    deposited: _,
+                    withdrawn: _,
+                },
+            ) = holder.unwrap();
 
-            assert!(!claimed_winnings || winner.is_some());
+            assert!(!claimed_winnings || has_result || void_prices.is_some());
             assert_eq!(outcomes.len(), market_outcomes.len());
 
             let mut has_tokens = false;
@@ -78,6 +152,11 @@ pub fn sanity(store: &dyn Storage, env: &Env) {
             if has_tokens || !shares.is_zero() {
                 computed_total_wallets += 1;
             }
+            // Holders from before the index existed are added in batches after migrating
+            let indexed = WALLET_MARKETS.has(store, (&addr, market_id));
+            if indexed || backfilled {
+                assert_eq!(indexed, has_tokens || !shares.is_zero());
+            }
 
             if !shares.is_zero() {
                 computed_lp_wallets += 1;
@@ -96,4 +175,7 @@ pub fn sanity(store: &dyn Storage, env: &Env) {
             assert_eq!(computed_wallets[outcome.id.usize()], outcome.wallets);
         }
     }
+
+    // Every referral balance belongs to a market
+    assert!(referral_fees.is_empty());
 }
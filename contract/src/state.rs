@@ -1,15 +1,42 @@
+use cw_utils::Expiration;
+
 use crate::prelude::*;
 
 pub const ADMIN: Item<Addr> = Item::new("admin");
 
 pub const APPOINTED_ADMIN: Item<Addr> = Item::new("appointed-admin");
 
+pub const CONFIG: Item<Config> = Item::new("config");
+
 pub const LAST_MARKET_ID: Item<MarketId> = Item::new("last-market-id");
 
 pub const MARKETS: Map<MarketId, StoredMarket> = Map::new("markets");
 
 pub const HOLDERS: Map<(MarketId, &Addr), ShareInfo> = Map::new("holders");
 
+/// Index of the markets where each wallet holds tokens or LP shares.
+///
+/// Maintained by [ShareInfo::save].
+pub const WALLET_MARKETS: Map<(&Addr, MarketId), ()> = Map::new("wallet-markets");
+
+/// Progress of adding holders from before [WALLET_MARKETS] existed to the index.
+pub const WALLET_MARKETS_BACKFILL: Item<WalletMarketsBackfill> =
+    Item::new("wallet-markets-backfill");
+
+/// Price snapshots for each market, keyed by the start of their time bucket in seconds.
+pub const PRICE_HISTORY: Map<(MarketId, u64), PriceSnapshot> = Map::new("price-history");
+
+/// Start of the price history bucket containing the given time, in seconds.
+pub(crate) fn price_bucket(timestamp: Timestamp) -> u64 {
+    timestamp.seconds() / PRICE_HISTORY_BUCKET_SECONDS * PRICE_HISTORY_BUCKET_SECONDS
+}
+
+/// Referral fees earned on each market, keyed by referrer.
+pub const REFERRAL_FEES: Map<(&Addr, MarketId), ReferralFees> = Map::new("referral-fees");
+
+/// cw1155 operator approvals, keyed by owner and operator.
+pub const APPROVALS: Map<(&Addr, &Addr), Expiration> = Map::new("approvals");
+
 impl ShareInfo {
     pub fn load(
         store: &dyn Storage,
@@ -35,6 +62,11 @@ impl ShareInfo {
         addr: &Addr,
     ) -> StdResult<()> {
         assert_eq!(market.outcomes.len(), self.outcomes.len());
+        if self.has_position() {
+            WALLET_MARKETS.save(store, (addr, market.id), &())?;
+        } else {
+            WALLET_MARKETS.remove(store, (addr, market.id));
+        }
         HOLDERS.save(store, (market.id, addr), self)
     }
 
@@ -89,6 +121,25 @@ impl ShareInfo {
     pub(crate) fn has_tokens(&self) -> bool {
         self.outcomes.iter().any(|token| !token.is_zero())
     }
+
+    /// Collateral owed to this wallet if each token pays out the given amount, including its share of the pool.
+    pub(crate) fn payout(
+        &self,
+        market: &StoredMarket,
+        payouts: &[Decimal256],
+    ) -> Result<Collateral> {
+        let mut total = Collateral::zero();
+        for (outcome, payout) in market.outcomes.iter().zip(payouts) {
+            let tokens = self.get_outcome(market, outcome.id, true)?;
+            total += Collateral(tokens.0) * *payout;
+        }
+        Ok(total)
+    }
+
+    /// Does this wallet hold any tokens or LP shares?
+    pub(crate) fn has_position(&self) -> bool {
+        self.has_tokens() || !self.shares.is_zero()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -98,19 +149,61 @@ pub struct StoredMarket {
     pub description: String,
     pub arbitrator: Addr,
     pub outcomes: Vec<StoredOutcome>,
-    pub denom: String,
+    pub denom: Denom,
     pub deposit_fee: Decimal256,
     pub withdrawal_fee: Decimal256,
+    /// Multipliers applied to the fees as the deposit stop date approaches
+    #[serde(default)]
+    pub fee_schedule: Vec<FeePoint>,
+    /// Oracle price used to resolve the market
+    #[serde(default)]
+    pub resolution: Option<StoredResolutionSource>,
+    /// Range of a scalar market
+    #[serde(default)]
+    pub scalar: Option<ScalarRange>,
     pub pool_size: Collateral,
     pub deposit_stop_date: Timestamp,
     pub withdrawal_stop_date: Timestamp,
+    /// The finalized winner of a categorical market
     pub winner: Option<OutcomeId>,
+    /// The finalized result of a scalar market
+    #[serde(default)]
+    pub scalar_result: Option<SignedDecimal256>,
+    /// How long a proposed winner can be disputed before it is final.
+    #[serde(default)]
+    pub dispute_period_seconds: u64,
+    /// Winner proposed by the arbitrator, still within its dispute period.
+    #[serde(default)]
+    pub proposed_winner: Option<ProposedWinner>,
+    /// Final outcome prices, set when the market is voided instead of resolved.
+    ///
+    /// Holders are refunded their tokens valued at these prices.
+    #[serde(default)]
+    pub void_prices: Option<Vec<Decimal256>>,
     pub house: Addr,
     pub total_wallets: u32,
     /// Total shares across all wallets
     pub lp_shares: LpShare,
     /// Number of wallets holding LP shares
     pub lp_wallets: u32,
+    /// Trading activity on this market
+    #[serde(default)]
+    pub stats: MarketStats,
+    /// Bond posted by a permissionless creator, until it is returned or slashed
+    #[serde(default)]
+    pub creator_bond: Option<CreatorBond>,
+    /// Share of each fee set aside for the protocol, fixed when the market is created
+    #[serde(default)]
+    pub protocol_fee_share: Decimal256,
+    /// Protocol fees not yet claimed, held outside of the pool
+    #[serde(default)]
+    pub protocol_fees: Collateral,
+    /// Share of each referred deposit fee paid to the referrer, fixed when the market is created
+    #[serde(default)]
+    pub referral_fee_share: Decimal256,
+    /// Referral fees not yet claimed, held outside of the pool
+    #[serde(default)]
+    pub referral_fees: Collateral,
 }
 
 impl StoredMarket {
@@ -120,6 +213,145 @@ impl StoredMarket {
             .ok_or(Error::MarketNotFound { id })
     }
 
+    pub fn status(&self, now: Timestamp) -> MarketStatus {
+        if self.void_prices.is_some() {
+            MarketStatus::Voided
+        } else if self.has_result() {
+            MarketStatus::Resolved
+        } else if self.proposed_winner.is_some() {
+            MarketStatus::WinnerProposed
+        } else if now >= self.deposit_stop_date {
+            MarketStatus::AwaitingResolution
+        } else if now >= self.withdrawal_stop_date {
+            MarketStatus::WithdrawalsClosed
+        } else {
+            MarketStatus::Open
+        }
+    }
+
+    /// Has a winner or scalar result been finalized?
+    pub(crate) fn has_result(&self) -> bool {
+        self.winner.is_some() || self.scalar_result.is_some()
+    }
+
+    /// Check that a proposed result matches the kind of market.
+    pub(crate) fn assert_valid_result(
+        &self,
+        outcome: Option<OutcomeId>,
+        scalar_result: Option<SignedDecimal256>,
+    ) -> Result<()> {
+        match (&self.scalar, outcome, scalar_result) {
+            (None, Some(outcome), None) => {
+                self.get_outcome(outcome)?;
+                Ok(())
+            }
+            (Some(_), None, Some(_)) => Ok(()),
+            (None, _, _) => Err(Error::NotScalarMarket { id: self.id }),
+            (Some(_), _, _) => Err(Error::ScalarMarket { id: self.id }),
+        }
+    }
+
+    pub(crate) fn assert_not_voided(&self) -> Result<()> {
+        if self.void_prices.is_some() {
+            Err(Error::MarketVoided { id: self.id })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that no winner has been proposed or set, and the market isn't voided.
+    pub(crate) fn assert_unresolved(&self) -> Result<()> {
+        self.assert_not_voided()?;
+        if self.has_result() {
+            Err(Error::WinnerAlreadySet { id: self.id })
+        } else if self.proposed_winner.is_some() {
+            Err(Error::WinnerAlreadyProposed { id: self.id })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that a proposed winner, if any, is still within its dispute period.
+    pub(crate) fn assert_dispute_open(&self, now: Timestamp) -> Result<()> {
+        match &self.proposed_winner {
+            Some(proposed) if now >= proposed.dispute_ends => Err(Error::DisputePeriodOver {
+                id: self.id,
+                now,
+                dispute_ends: proposed.dispute_ends,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn assert_deposits_open(&self, now: Timestamp) -> Result<()> {
+        self.assert_not_voided()?;
+        if now >= self.deposit_stop_date {
+            Err(Error::DepositsStopped {
+                id: self.id,
+                now,
+                deposit_stop_date: self.deposit_stop_date,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn assert_withdrawals_open(&self, now: Timestamp) -> Result<()> {
+        self.assert_not_voided()?;
+        if now >= self.withdrawal_stop_date {
+            Err(Error::WithdrawalsStopped {
+                id: self.id,
+                now,
+                withdrawal_stop_date: self.withdrawal_stop_date,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Deposit fee ratio in effect at the given time.
+    pub fn deposit_fee_at(&self, now: Timestamp) -> Decimal256 {
+        self.deposit_fee * self.fee_multiplier(now)
+    }
+
+    /// Withdrawal fee ratio in effect at the given time.
+    pub fn withdrawal_fee_at(&self, now: Timestamp) -> Decimal256 {
+        self.withdrawal_fee * self.fee_multiplier(now)
+    }
+
+    /// Interpolate the fee schedule at the given time.
+    fn fee_multiplier(&self, now: Timestamp) -> Decimal256 {
+        let remaining = self
+            .deposit_stop_date
+            .seconds()
+            .saturating_sub(now.seconds());
+        let (Some(first), Some(last)) = (self.fee_schedule.first(), self.fee_schedule.last())
+        else {
+            return Decimal256::one();
+        };
+        if remaining >= first.seconds_remaining {
+            return first.multiplier;
+        }
+        if remaining <= last.seconds_remaining {
+            return last.multiplier;
+        }
+        for window in self.fee_schedule.windows(2) {
+            let (before, after) = (&window[0], &window[1]);
+            if remaining >= after.seconds_remaining {
+                let progress = Decimal256::from_ratio(
+                    before.seconds_remaining - remaining,
+                    before.seconds_remaining - after.seconds_remaining,
+                );
+                return if after.multiplier >= before.multiplier {
+                    before.multiplier + (after.multiplier - before.multiplier) * progress
+                } else {
+                    before.multiplier - (before.multiplier - after.multiplier) * progress
+                };
+            }
+        }
+        unreachable!("fee schedule points are in decreasing order")
+    }
+
     pub(crate) fn get_outcome(&self, outcome: OutcomeId) -> Result<&StoredOutcome> {
         self.outcomes
             .get(outcome.usize())
@@ -140,6 +372,338 @@ impl StoredMarket {
                 outcome,
             })
     }
+
+    /// Collateral paid out per token of each outcome.
+    ///
+    /// Fails if the market has not been resolved or voided yet.
+    pub(crate) fn payouts(&self) -> Result<Vec<Decimal256>> {
+        if let Some(prices) = &self.void_prices {
+            return Ok(prices.clone());
+        }
+        if let Some(proposed) = &self.proposed_winner {
+            return Err(Error::WinnerNotFinalized {
+                id: self.id,
+                dispute_ends: proposed.dispute_ends,
+            });
+        }
+        if let Some(result) = self.scalar_result {
+            return self.scalar_payouts(result);
+        }
+        let winner = self.winner.ok_or(Error::NoWinnerSet { id: self.id })?;
+        Ok(self
+            .outcomes
+            .iter()
+            .map(|outcome| {
+                if outcome.id == winner {
+                    Decimal256::one()
+                } else {
+                    Decimal256::zero()
+                }
+            })
+            .collect())
+    }
+
+    /// LONG and SHORT payouts for a scalar result, clamped to the market's range.
+    fn scalar_payouts(&self, result: SignedDecimal256) -> Result<Vec<Decimal256>> {
+        let ScalarRange { min, max } = self
+            .scalar
+            .as_ref()
+            .ok_or(Error::NotScalarMarket { id: self.id })?;
+        let long = if result <= *min {
+            Decimal256::zero()
+        } else if result >= *max {
+            Decimal256::one()
+        } else {
+            result
+                .checked_sub(*min)?
+                .checked_div(max.checked_sub(*min)?)?
+                .try_into()?
+        };
+        Ok(vec![long, Decimal256::one() - long])
+    }
+
+    /// Are bettors' outcome tokens issued as native coins?
+    pub fn is_native(&self) -> bool {
+        self.outcomes
+            .iter()
+            .all(|outcome| outcome.native_denom.is_some())
+    }
+
+    /// Stop tracking native tokens returned to the contract before resolution.
+    pub(crate) fn return_minted(&mut self, tokens: &[Token]) {
+        for (outcome, tokens) in self.outcomes.iter_mut().zip(tokens) {
+            outcome.minted -= *tokens;
+        }
+    }
+
+    /// Store the current prices in the market's price history.
+    ///
+    /// Replaces any earlier snapshot in the same time bucket.
+    pub(crate) fn snapshot_prices(&self, store: &mut dyn Storage, now: Timestamp) -> StdResult<()> {
+        PRICE_HISTORY.save(
+            store,
+            (self.id, price_bucket(now)),
+            &PriceSnapshot {
+                timestamp: now,
+                prices: self.prices(),
+                pool_size: self.pool_size,
+            },
+        )
+    }
+
+    /// Update the wallet counters after a holder's position changed from `old` to `new`.
+    pub(crate) fn update_wallet_counts(&mut self, old: &ShareInfo, new: &ShareInfo) {
+        assert_eq!(old.outcomes.len(), self.outcomes.len());
+        assert_eq!(new.outcomes.len(), self.outcomes.len());
+        for (outcome, (old, new)) in self
+            .outcomes
+            .iter_mut()
+            .zip(old.outcomes.iter().zip(new.outcomes.iter()))
+        {
+            match (old.is_zero(), new.is_zero()) {
+                (true, false) => outcome.wallets += 1,
+                (false, true) => outcome.wallets -= 1,
+                _ => (),
+            }
+        }
+        match (old.shares.is_zero(), new.shares.is_zero()) {
+            (true, false) => self.lp_wallets += 1,
+            (false, true) => self.lp_wallets -= 1,
+            _ => (),
+        }
+        match (old.has_position(), new.has_position()) {
+            (false, true) => self.total_wallets += 1,
+            (true, false) => self.total_wallets -= 1,
+            _ => (),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct ProposedWinner {
+    /// Winner of a categorical market
+    pub outcome: Option<OutcomeId>,
+    /// Result of a scalar market
+    #[serde(default)]
+    pub scalar_result: Option<SignedDecimal256>,
+    /// When the proposal can be finalized
+    pub dispute_ends: Timestamp,
+}
+
+/// A [ResolutionSource] with a validated oracle address.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct StoredResolutionSource {
+    pub oracle: Addr,
+    pub feed_id: String,
+    pub strike: Decimal256,
+    pub comparator: Comparator,
+    pub resolution_time: Timestamp,
+    pub max_publish_delay_seconds: u64,
+    pub max_confidence_ratio: Decimal256,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletMarketsBackfill {
+    /// Holders up to and including `last`, in [HOLDERS] order, are indexed
+    InProgress {
+        last: Option<(MarketId, Addr)>,
+    },
+    Done,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct CreatorBond {
+    pub creator: Addr,
+    /// Held in the market's collateral denom
+    pub amount: Collateral,
+}
+
+/// Used when migrating from versions without a config.
+///
+/// No collateral is allowed, so the admin needs to update the config before
+/// creating new markets.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            permissionless: false,
+            collateral: vec![],
+            max_deposit_fee: Decimal256::percent(10),
+            max_withdrawal_fee: Decimal256::percent(10),
+            max_outcomes: MAX_OUTCOMES.try_into().expect("MAX_OUTCOMES fits in a u32"),
+            min_duration_seconds: 60 * 60,
+            max_duration_seconds: 365 * 24 * 60 * 60,
+            protocol_fee_share: Decimal256::zero(),
+            treasury: None,
+            referral_fee_share: Decimal256::zero(),
+            max_publish_delay_seconds: default_max_publish_delay_seconds(),
+            max_confidence_ratio: default_max_confidence_ratio(),
+            min_dispute_period_seconds: default_min_dispute_period_seconds(),
+            oracles: vec![],
+            referrers: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Check the config, returning it with the treasury address normalized.
+    pub(crate) fn validate(mut self, api: &dyn Api) -> Result<Self> {
+        let max_outcomes = usize::try_from(self.max_outcomes)?;
+        if !(2..=MAX_OUTCOMES).contains(&max_outcomes) {
+            return Err(Error::InvalidConfig {
+                msg: format!("max_outcomes must be between 2 and {MAX_OUTCOMES}"),
+            });
+        }
+        if self.max_deposit_fee >= Decimal256::one() || self.max_withdrawal_fee >= Decimal256::one()
+        {
+            return Err(Error::InvalidConfig {
+                msg: "Maximum fees must be less than 1".to_owned(),
+            });
+        }
+        if self.protocol_fee_share + self.referral_fee_share > Decimal256::one() {
+            return Err(Error::InvalidConfig {
+                msg: "protocol_fee_share and referral_fee_share must add up to at most 1"
+                    .to_owned(),
+            });
+        }
+        match &self.treasury {
+            Some(treasury) => {
+                self.treasury = Some(api.addr_validate(treasury)?.into_string());
+            }
+            None if !self.protocol_fee_share.is_zero() => {
+                return Err(Error::InvalidConfig {
+                    msg: "A treasury is required to collect protocol fees".to_owned(),
+                });
+            }
+            None => (),
+        }
+        if self.permissionless && self.min_dispute_period_seconds == 0 {
+            return Err(Error::InvalidConfig {
+                msg: "min_dispute_period_seconds must be nonzero for permissionless markets"
+                    .to_owned(),
+            });
+        }
+        for addr in self.oracles.iter_mut().chain(&mut self.referrers) {
+            *addr = api.addr_validate(addr)?.into_string();
+        }
+        if self.min_duration_seconds > self.max_duration_seconds {
+            return Err(Error::InvalidConfig {
+                msg: "min_duration_seconds is above max_duration_seconds".to_owned(),
+            });
+        }
+        for (idx, collateral) in self.collateral.iter().enumerate() {
+            collateral.denom.validate(api)?;
+            if collateral.min_liquidity.is_zero()
+                || collateral.min_liquidity > collateral.max_liquidity
+            {
+                return Err(Error::InvalidConfig {
+                    msg: format!("Invalid liquidity range for {}", collateral.denom),
+                });
+            }
+            if self.collateral[..idx]
+                .iter()
+                .any(|other| other.denom == collateral.denom)
+            {
+                return Err(Error::InvalidConfig {
+                    msg: format!("{} is listed more than once", collateral.denom),
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn get_collateral(&self, denom: &Denom) -> Result<&CollateralConfig> {
+        self.collateral
+            .iter()
+            .find(|collateral| collateral.denom == *denom)
+            .ok_or_else(|| Error::CollateralNotAllowed {
+                denom: denom.to_string(),
+            })
+    }
+
+    /// Check the parameters of a new market against the configured limits.
+    pub(crate) fn assert_market_allowed(
+        &self,
+        collateral: &CollateralConfig,
+        deposit_fee: Decimal256,
+        withdrawal_fee: Decimal256,
+        total_outcomes: usize,
+        funds: Collateral,
+        duration_seconds: u64,
+    ) -> Result<()> {
+        for (fee, max) in [
+            (deposit_fee, self.max_deposit_fee),
+            (withdrawal_fee, self.max_withdrawal_fee),
+        ] {
+            if fee > max {
+                return Err(Error::FeeAboveMaximum { fee, max });
+            }
+        }
+        if total_outcomes > usize::try_from(self.max_outcomes)? {
+            return Err(Error::UnsupportedOutcomes { total_outcomes });
+        }
+
+        let min = Collateral(collateral.min_liquidity.into());
+        if funds.0 < min.0 {
+            return Err(Error::LiquidityBelowMinimum { funds, min });
+        }
+        let max = Collateral(collateral.max_liquidity.into());
+        if funds.0 > max.0 {
+            return Err(Error::LiquidityAboveMaximum { funds, max });
+        }
+
+        if duration_seconds < self.min_duration_seconds {
+            return Err(Error::DurationTooShort {
+                duration_seconds,
+                min: self.min_duration_seconds,
+            });
+        }
+        if duration_seconds > self.max_duration_seconds {
+            return Err(Error::DurationTooLong {
+                duration_seconds,
+                max: self.max_duration_seconds,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct ReferralFees {
+    /// Collateral that can be claimed with [ExecuteMsg::ClaimReferralFees]
+    pub unclaimed: Collateral,
+    /// All fees earned, including claimed fees
+    pub total: Collateral,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PriceSnapshot {
+    pub timestamp: Timestamp,
+    pub prices: Vec<Decimal256>,
+    pub pool_size: Collateral,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case", default)]
+pub struct MarketStats {
+    /// Collateral spent on bets, including fees
+    pub deposit_volume: Collateral,
+    /// Collateral received from selling tokens, including fees
+    pub withdrawal_volume: Collateral,
+    pub deposit_fees: Collateral,
+    pub withdrawal_fees: Collateral,
+    /// Portion of all fees set aside for the protocol, including claimed fees
+    pub protocol_fees: Collateral,
+    /// Portion of all fees set aside for referrers, including claimed fees
+    pub referral_fees: Collateral,
+    /// Number of bets and sales
+    pub trades: u64,
+    pub last_trade: Option<Timestamp>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -148,15 +712,29 @@ pub struct StoredOutcome {
     pub label: String,
     pub pool_tokens: Token,
     /// Count of wallets holding tokens
+    ///
+    /// Only internal balances are counted, not native coins.
     pub wallets: u32,
+    /// Tokenfactory denom, if this outcome is issued as native coins
+    #[serde(default)]
+    pub native_denom: Option<String>,
+    /// Tokens currently in circulation as native coins
+    #[serde(default = "Token::zero")]
+    pub minted: Token,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ShareInfo {
     pub outcomes: Vec<Token>,
     /// LP shares held by this wallet
     pub shares: LpShare,
     pub claimed_winnings: bool,
+    /// Collateral paid in through bets, liquidity and minted sets
+    #[serde(default = "Collateral::zero")]
+    pub deposited: Collateral,
+    /// Collateral paid out from sales, liquidity withdrawals, redeemed sets and winnings
+    #[serde(default = "Collateral::zero")]
+    pub withdrawn: Collateral,
 }
 
 impl ShareInfo {
@@ -167,6 +745,8 @@ impl ShareInfo {
                 .collect(),
             shares: LpShare::zero(),
             claimed_winnings: false,
+            deposited: Collateral::zero(),
+            withdrawn: Collateral::zero(),
         }
     }
 }
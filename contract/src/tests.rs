@@ -1,17 +1,106 @@
 use std::{cell::RefCell, str::FromStr};
 
-use cosmwasm_std::{Addr, Uint256};
-use cw_multi_test::{error::AnyResult, App, AppResponse, ContractWrapper, Executor};
+use cosmwasm_std::{
+    testing::{MockApi, MockStorage},
+    to_json_binary, Addr, BankMsg, BlockInfo, CustomQuery, Empty, Event, Int256, Uint256, Uint512,
+};
+use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+use cw_multi_test::{
+    error::{bail, AnyResult},
+    App, AppBuilder, AppResponse, BankKeeper, BankSudo, ContractWrapper, CosmosRouter,
+    DistributionKeeper, Executor, FailingModule, GovFailingModule, IbcFailingModule, StakeKeeper,
+    Stargate, SudoMsg, WasmKeeper,
+};
+use cw_utils::Expiration;
 use proptest::prelude::*;
+use prost::Message;
+use serde::de::DeserializeOwned;
 
 use crate::{
-    cpmm::Buy,
+    cpmm::{Buy, Sell},
     execute::{initial_outcomes, InitialOutcomes},
+    oracle::{OracleQueryMsg, Price, PriceFeed, PriceFeedResponse},
     prelude::*,
+    tokenfactory::{self, MsgBurn, MsgCreateDenom, MsgMint},
 };
 
+/// Minimal tokenfactory module backed by the multi-test bank.
+struct MockTokenFactory;
+
+impl Stargate for MockTokenFactory {
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        sender: Addr,
+        type_url: String,
+        value: Binary,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let to_coin = |coin: Option<tokenfactory::ProtoCoin>| -> AnyResult<Coin> {
+            let coin = coin.unwrap();
+            if !coin.denom.starts_with(&format!("factory/{sender}/")) {
+                bail!("{sender} cannot mint or burn {}", coin.denom);
+            }
+            Ok(Coin {
+                denom: coin.denom,
+                amount: coin.amount.parse()?,
+            })
+        };
+        match type_url.as_str() {
+            tokenfactory::CREATE_DENOM_TYPE_URL => {
+                MsgCreateDenom::decode(value.as_slice())?;
+                Ok(AppResponse::default())
+            }
+            tokenfactory::MINT_TYPE_URL => {
+                let msg = MsgMint::decode(value.as_slice())?;
+                let coin = to_coin(msg.amount)?;
+                router.sudo(
+                    api,
+                    storage,
+                    block,
+                    SudoMsg::Bank(BankSudo::Mint {
+                        to_address: msg.mint_to_address,
+                        amount: vec![coin],
+                    }),
+                )
+            }
+            tokenfactory::BURN_TYPE_URL => {
+                let msg = MsgBurn::decode(value.as_slice())?;
+                let coin = to_coin(msg.amount)?;
+                router.execute(
+                    api,
+                    storage,
+                    block,
+                    Addr::unchecked(msg.burn_from_address),
+                    BankMsg::Burn { amount: vec![coin] }.into(),
+                )
+            }
+            _ => bail!("Unexpected stargate message {type_url}"),
+        }
+    }
+}
+
+type TestApp = App<
+    BankKeeper,
+    MockApi,
+    MockStorage,
+    FailingModule<Empty, Empty, Empty>,
+    WasmKeeper<Empty, Empty>,
+    StakeKeeper,
+    DistributionKeeper,
+    IbcFailingModule,
+    GovFailingModule,
+    MockTokenFactory,
+>;
+
 struct Predict {
-    app: RefCell<App>,
+    app: RefCell<TestApp>,
     contract: Addr,
     admin: Addr,
     arbitrator: Addr,
@@ -22,41 +111,91 @@ struct Predict {
 
 const DENOM: &str = "satoshi";
 
+fn test_config() -> Config {
+    Config {
+        collateral: vec![CollateralConfig {
+            denom: Denom::Native(DENOM.to_owned()),
+            creator_bond: Uint128::zero(),
+            min_liquidity: 1u8.into(),
+            max_liquidity: 1_000_000u32.into(),
+        }],
+        ..Config::default()
+    }
+}
+
+/// A market outside of the contract, for testing the CPMM math directly.
+fn test_market(outcomes: Vec<StoredOutcome>, pool_size: Collateral) -> StoredMarket {
+    let ts = Timestamp::from_nanos(1_000_000_202);
+    StoredMarket {
+        id: MarketId::one(),
+        title: "ATOM_USDT".to_owned(),
+        description: "Some desc".to_owned(),
+        arbitrator: Addr::unchecked("arbitrator"),
+        outcomes,
+        denom: Denom::Native(DENOM.to_owned()),
+        deposit_fee: "0.01".parse().unwrap(),
+        withdrawal_fee: "0.01".parse().unwrap(),
+        fee_schedule: vec![],
+        resolution: None,
+        scalar: None,
+        pool_size,
+        deposit_stop_date: ts.plus_days(2),
+        withdrawal_stop_date: ts.plus_days(1),
+        winner: None,
+        scalar_result: None,
+        dispute_period_seconds: 0,
+        proposed_winner: None,
+        void_prices: None,
+        house: Addr::unchecked("house"),
+        total_wallets: 0,
+        lp_shares: LpShare::zero(),
+        lp_wallets: 0,
+        stats: MarketStats::default(),
+        creator_bond: None,
+        protocol_fee_share: Decimal256::zero(),
+        protocol_fees: Collateral::zero(),
+        referral_fee_share: Decimal256::zero(),
+        referral_fees: Collateral::zero(),
+    }
+}
+
 impl Predict {
     fn new() -> Self {
         let admin = Addr::unchecked("admin");
         let arbitrator = Addr::unchecked("arbitrator");
         let house = Addr::unchecked("house");
         let better = Addr::unchecked("better");
-        let mut app = App::new(|router, _, storage| {
-            router
-                .bank
-                .init_balance(
-                    storage,
-                    &admin,
-                    vec![Coin {
-                        denom: DENOM.to_owned(),
-                        amount: 1_000_000_000u32.into(),
-                    }],
-                )
-                .unwrap();
-            router
-                .bank
-                .init_balance(
-                    storage,
-                    &better,
-                    vec![Coin {
-                        denom: DENOM.to_owned(),
-                        amount: 1_000_000_000u32.into(),
-                    }],
-                )
-                .unwrap();
-        });
-        let wrapper = Box::new(ContractWrapper::new(
-            crate::execute,
-            crate::instantiate,
-            crate::query,
-        ));
+        let mut app =
+            AppBuilder::new()
+                .with_stargate(MockTokenFactory)
+                .build(|router, _, storage| {
+                    router
+                        .bank
+                        .init_balance(
+                            storage,
+                            &admin,
+                            vec![Coin {
+                                denom: DENOM.to_owned(),
+                                amount: 1_000_000_000u32.into(),
+                            }],
+                        )
+                        .unwrap();
+                    router
+                        .bank
+                        .init_balance(
+                            storage,
+                            &better,
+                            vec![Coin {
+                                denom: DENOM.to_owned(),
+                                amount: 1_000_000_000u32.into(),
+                            }],
+                        )
+                        .unwrap();
+                });
+        let wrapper = Box::new(
+            ContractWrapper::new(crate::execute, crate::instantiate, crate::query)
+                .with_migrate(crate::migrate),
+        );
         let id = app.store_code(wrapper);
         let contract = app
             .instantiate_contract(
@@ -64,10 +203,11 @@ impl Predict {
                 admin.clone(),
                 &InstantiateMsg {
                     admin: admin.clone().into_string(),
+                    config: test_config(),
                 },
                 &[],
                 "predict",
-                None,
+                Some(admin.clone().into_string()),
             )
             .unwrap();
         let params = AddMarketParams {
@@ -84,12 +224,17 @@ impl Predict {
                     initial_amount: Token(900u16.into()),
                 },
             ],
-            denom: DENOM.to_owned(),
+            denom: Denom::Native(DENOM.to_owned()),
             deposit_fee: "0.01".parse().unwrap(),
             withdrawal_fee: "0.02".parse().unwrap(),
             withdrawal_stop_date: app.block_info().time.plus_days(1),
             deposit_stop_date: app.block_info().time.plus_days(2),
+            dispute_period_seconds: 0,
             house: house.clone().into_string(),
+            native_tokens: false,
+            fee_schedule: vec![],
+            resolution: None,
+            scalar: None,
         };
         app.execute_contract(
             admin.clone(),
@@ -114,33 +259,85 @@ impl Predict {
         }
     }
 
+    fn market_params(&self) -> AddMarketParams {
+        let now = self.app.borrow().block_info().time;
+        AddMarketParams {
+            title: "Test market".to_owned(),
+            description: "Test description".to_owned(),
+            arbitrator: self.arbitrator.clone().into_string(),
+            outcomes: vec![
+                OutcomeDef {
+                    label: "Yes".to_owned(),
+                    initial_amount: Token(100u16.into()),
+                },
+                OutcomeDef {
+                    label: "No".to_owned(),
+                    initial_amount: Token(900u16.into()),
+                },
+            ],
+            denom: Denom::Native(DENOM.to_owned()),
+            deposit_fee: "0.01".parse().unwrap(),
+            withdrawal_fee: "0.02".parse().unwrap(),
+            withdrawal_stop_date: now.plus_days(1),
+            deposit_stop_date: now.plus_days(2),
+            dispute_period_seconds: 0,
+            house: self.house.clone().into_string(),
+            native_tokens: false,
+            fee_schedule: vec![],
+            resolution: None,
+            scalar: None,
+        }
+    }
+
+    fn add_market(
+        &self,
+        sender: &Addr,
+        params: AddMarketParams,
+        funds: u64,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            &ExecuteMsg::AddMarket {
+                params: params.into(),
+            },
+            Some(funds),
+        )
+    }
+
     fn execute(
         &self,
         sender: &Addr,
         msg: &ExecuteMsg,
         funds: Option<u64>,
     ) -> AnyResult<AppResponse> {
-        let helper = |funds| {
-            self.app.borrow_mut().execute_contract(
-                sender.clone(),
-                self.contract.clone(),
-                msg,
-                funds,
-            )
-        };
         match funds {
-            Some(funds) => helper(&[Coin {
-                denom: DENOM.to_owned(),
-                amount: funds.into(),
-            }]),
-            None => helper(&[]),
+            Some(funds) => self.execute_with_coins(
+                sender,
+                msg,
+                &[Coin {
+                    denom: DENOM.to_owned(),
+                    amount: funds.into(),
+                }],
+            ),
+            None => self.execute_with_coins(sender, msg, &[]),
         }
     }
 
-    fn set_winner(&self, sender: &Addr, outcome: u8) -> AnyResult<AppResponse> {
+    fn execute_with_coins(
+        &self,
+        sender: &Addr,
+        msg: &ExecuteMsg,
+        coins: &[Coin],
+    ) -> AnyResult<AppResponse> {
+        self.app
+            .borrow_mut()
+            .execute_contract(sender.clone(), self.contract.clone(), msg, coins)
+    }
+
+    fn propose_winner(&self, sender: &Addr, outcome: u8) -> AnyResult<AppResponse> {
         self.execute(
             sender,
-            &ExecuteMsg::SetWinner {
+            &ExecuteMsg::ProposeWinner {
                 id: self.id,
                 outcome: outcome.into(),
             },
@@ -148,6 +345,31 @@ impl Predict {
         )
     }
 
+    fn finalize_winner(&self) -> AnyResult<AppResponse> {
+        self.execute(
+            &self.better,
+            &ExecuteMsg::FinalizeWinner { id: self.id },
+            None,
+        )
+    }
+
+    /// Propose a winner and finalize it immediately, for markets with no dispute period.
+    fn set_winner(&self, sender: &Addr, outcome: u8) -> AnyResult<AppResponse> {
+        self.propose_winner(sender, outcome)?;
+        self.finalize_winner()
+    }
+
+    fn void_market(&self, sender: &Addr) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            &ExecuteMsg::VoidMarket {
+                id: self.id,
+                invalid: false,
+            },
+            None,
+        )
+    }
+
     fn jump_days(&self, days: u64) {
         self.app.borrow_mut().update_block(|b| {
             b.height += days * 100;
@@ -162,7 +384,25 @@ impl Predict {
     }
 
     fn provide(&self, sender: &Addr, funds: u64) -> AnyResult<AppResponse> {
-        self.execute(sender, &ExecuteMsg::Provide { id: self.id }, Some(funds))
+        self.execute(
+            sender,
+            &ExecuteMsg::Provide {
+                id: self.id,
+                referrer: None,
+            },
+            Some(funds),
+        )
+    }
+
+    fn withdraw_liquidity(&self, sender: &Addr, shares: LpShare) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            &ExecuteMsg::WithdrawLiquidity {
+                id: self.id,
+                shares,
+            },
+            None,
+        )
     }
 
     fn place_bet(&self, sender: &Addr, outcome: u8, funds: u64) -> AnyResult<AppResponse> {
@@ -182,6 +422,8 @@ impl Predict {
                 id: self.id,
                 outcome: outcome.into(),
                 liquidity,
+                min_tokens: None,
+                referrer: None,
             },
             Some(funds),
         )
@@ -214,6 +456,8 @@ impl Predict {
             outcomes,
             claimed_winnings: _,
             shares: _,
+            deposited: _,
+            withdrawn: _,
         } = self.query_holder(better)?;
         outcomes
             .get(usize::from(outcome))
@@ -244,6 +488,7 @@ impl Predict {
                 id: self.id,
                 outcome: outcome.into(),
                 tokens,
+                min_collateral: None,
             },
             None,
         )
@@ -279,12 +524,17 @@ fn non_admin_cannot_add_market() {
                 initial_amount: Token(900u16.into()),
             },
         ],
-        denom: DENOM.to_owned(),
+        denom: Denom::Native(DENOM.to_owned()),
         deposit_fee: "0.01".parse().unwrap(),
         withdrawal_fee: "0.01".parse().unwrap(),
         withdrawal_stop_date: app.app.borrow().block_info().time.plus_days(1),
         deposit_stop_date: app.app.borrow().block_info().time.plus_days(2),
+        dispute_period_seconds: 0,
         house: app.house.clone().into_string(),
+        native_tokens: false,
+        fee_schedule: vec![],
+        resolution: None,
+        scalar: None,
     };
     // Better is try to add a market
     app.app
@@ -304,113 +554,2122 @@ fn non_admin_cannot_add_market() {
 }
 
 #[test]
-fn sanity() {
-    let app = Predict::new();
-    app.jump_days(3);
-    let amount_before = app.query_balance(&app.house).unwrap();
-    assert_eq!(Uint128::from(0u16), amount_before);
+fn protocol_fees() {
+    let mut app = Predict::new();
+    let treasury = Addr::unchecked("treasury");
+    let mut config = test_config();
+    config.protocol_fee_share = "0.5".parse().unwrap();
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::UpdateConfig {
+            config: config.clone(),
+        },
+        None,
+    )
+    .unwrap_err();
+    config.treasury = Some(treasury.to_string());
+    app.execute(&app.admin, &ExecuteMsg::UpdateConfig { config }, None)
+        .unwrap();
 
-    // Admin cannot set the winner
-    app.set_winner(&app.admin, 0).unwrap_err();
+    // Existing markets keep their fee split
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    assert!(app.query_latest_market().unwrap().protocol_fees.is_zero());
 
-    // Arbitrator can set the winner
-    app.set_winner(&app.arbitrator, 0).unwrap();
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+    app.id = MarketId(2);
+    let simulated = app
+        .query::<SimulateDepositResp>(&QueryMsg::SimulateDeposit {
+            id: app.id,
+            outcome: 0.into(),
+            amount: Collateral(1_000u16.into()),
+            liquidity: Decimal256::zero(),
+            referred: false,
+        })
+        .unwrap();
+    assert_eq!(simulated.fee, Collateral(10u8.into()));
+    assert_eq!(simulated.protocol_fee, Collateral(5u8.into()));
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.stats.deposit_fees, Collateral(10u8.into()));
+    assert_eq!(market.protocol_fees, Collateral(5u8.into()));
+    assert_eq!(market.stats.protocol_fees, Collateral(5u8.into()));
+    assert_eq!(market.pool_size, Collateral(1_995u16.into()));
+
+    let claim =
+        |sender: &Addr| app.execute(sender, &ExecuteMsg::ClaimProtocolFees { id: app.id }, None);
+    claim(&app.better).unwrap_err();
+    claim(&app.admin).unwrap();
+    claim(&app.admin).unwrap_err();
+    assert_eq!(app.query_balance(&treasury).unwrap(), Uint128::new(5));
+    let market = app.query_latest_market().unwrap();
+    assert!(market.protocol_fees.is_zero());
+    assert_eq!(market.stats.protocol_fees, Collateral(5u8.into()));
 
-    // House can claim its winnings
+    // Everything else in the pool still pays out
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    let simulated = app
+        .query::<SimulateWithdrawResp>(&QueryMsg::SimulateWithdraw {
+            id: app.id,
+            outcome: 0.into(),
+            tokens,
+        })
+        .unwrap();
+    assert!(!simulated.protocol_fee.is_zero());
+    assert!(simulated.protocol_fee.0 <= simulated.fee.0);
+    app.withdraw(&app.better, 0, tokens).unwrap();
+    assert!(!app.query_latest_market().unwrap().protocol_fees.is_zero());
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 1).unwrap();
     app.collect(&app.house).unwrap();
-    app.collect(&app.house).unwrap_err();
-
-    let amount_after = app.query_balance(&app.house).unwrap();
-    assert_eq!(Uint128::from(1000u16), amount_after);
+    claim(&app.admin).unwrap();
 }
 
 #[test]
-fn losing_bet() {
-    let app = Predict::new();
+fn referral_fees() {
+    let mut app = Predict::new();
+    let frontend = Addr::unchecked("frontend");
+    let mut config = test_config();
+    config.referral_fee_share = "0.4".parse().unwrap();
+    config.protocol_fee_share = "0.7".parse().unwrap();
+    config.treasury = Some("treasury".to_owned());
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::UpdateConfig {
+            config: config.clone(),
+        },
+        None,
+    )
+    .unwrap_err();
+    config.protocol_fee_share = Decimal256::zero();
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::UpdateConfig {
+            config: config.clone(),
+        },
+        None,
+    )
+    .unwrap();
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+    app.id = MarketId(2);
 
-    // Arbitrator doesn't have any funds
-    app.place_bet(&app.arbitrator, 1, 1_000).unwrap_err();
+    let deposit = |app: &Predict, sender: &Addr, referrer: &Addr| {
+        app.execute(
+            sender,
+            &ExecuteMsg::Deposit {
+                id: app.id,
+                outcome: 0.into(),
+                liquidity: Decimal256::zero(),
+                min_tokens: None,
+                referrer: Some(referrer.to_string()),
+            },
+            Some(1_000),
+        )
+    };
+    let query_fees = |app: &Predict| {
+        app.query::<ReferralFeesResp>(&QueryMsg::ReferralFees {
+            referrer: frontend.to_string(),
+            start_after: None,
+            limit: None,
+        })
+        .unwrap()
+        .markets
+    };
 
-    app.place_bet(&app.better, 1, 1_000).unwrap();
+    // Only registered partners can be referrers
+    let err = deposit(&app, &app.better, &frontend).unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::UnknownReferrer { .. }
+    ));
+    config.referrers = vec![frontend.to_string(), app.better.to_string()];
+    app.execute(&app.admin, &ExecuteMsg::UpdateConfig { config }, None)
+        .unwrap();
+    let err = deposit(&app, &app.better, &app.better).unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::SelfReferral
+    ));
+    let simulated: SimulateDepositResp = app
+        .query(&QueryMsg::SimulateDeposit {
+            id: app.id,
+            outcome: 0.into(),
+            amount: Collateral(1_000u16.into()),
+            liquidity: Decimal256::zero(),
+            referred: true,
+        })
+        .unwrap();
+    let res = deposit(&app, &app.better, &frontend).unwrap();
+    assert!(res.events.iter().any(|e| e.ty == "wasm-referral"));
+    assert_eq!(app.query_tokens(&app.better, 0).unwrap(), simulated.tokens);
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.stats.deposit_fees, Collateral(10u8.into()));
+    assert_eq!(market.referral_fees, Collateral(4u8.into()));
+    assert_eq!(market.stats.referral_fees, Collateral(4u8.into()));
+    assert_eq!(market.pool_size, Collateral(1_996u16.into()));
+
+    // Providing liquidity is attributed but pays nothing
+    let res = app
+        .execute(
+            &app.better,
+            &ExecuteMsg::Provide {
+                id: app.id,
+                referrer: Some(frontend.to_string()),
+            },
+            Some(1_000),
+        )
+        .unwrap();
+    assert!(res.events.iter().any(|e| e.ty == "wasm-referral"));
+
+    // Markets created before the share was set pay no referral fees
+    app.id = MarketId(1);
+    deposit(&app, &app.better, &frontend).unwrap();
+    assert!(app.query_latest_market().unwrap().referral_fees.is_zero());
+    app.id = MarketId(2);
+
+    let fees = query_fees(&app);
+    assert_eq!(fees.len(), 1);
+    assert_eq!(fees[0].id, MarketId(2));
+    assert_eq!(fees[0].unclaimed, Collateral(4u8.into()));
+    assert_eq!(fees[0].total, Collateral(4u8.into()));
+
+    let claim = |app: &Predict, sender: &Addr| {
+        app.execute(sender, &ExecuteMsg::ClaimReferralFees { id: app.id }, None)
+    };
+    claim(&app, &app.better).unwrap_err();
+    claim(&app, &frontend).unwrap();
+    claim(&app, &frontend).unwrap_err();
+    assert_eq!(app.query_balance(&frontend).unwrap(), Uint128::new(4));
+    let fees = query_fees(&app);
+    assert!(fees[0].unclaimed.is_zero());
+    assert_eq!(fees[0].total, Collateral(4u8.into()));
+    assert!(app.query_latest_market().unwrap().referral_fees.is_zero());
+}
 
-    app.jump_days(3);
-    let better_before = app.query_balance(&app.better).unwrap();
+#[test]
+fn fee_schedule() {
+    let mut app = Predict::new();
+    let hours = |hours: u64| hours * 60 * 60;
+    let point = |hours_remaining: u64, multiplier: &str| FeePoint {
+        seconds_remaining: hours(hours_remaining),
+        multiplier: multiplier.parse().unwrap(),
+    };
 
-    let house_before = app.query_balance(&app.house).unwrap();
-    assert_eq!(house_before, Uint128::from(0u16));
+    let mut params = app.market_params();
+    params.fee_schedule = vec![point(12, "3"), point(36, "1")];
+    app.add_market(&app.admin, params, 1_000).unwrap_err();
+    let mut params = app.market_params();
+    params.fee_schedule = vec![point(36, "1"), point(12, "20")];
+    let err = app
+        .add_market(&app.admin, params, 1_000)
+        .unwrap_err()
+        .downcast::<Error>()
+        .unwrap();
+    assert!(matches!(err, Error::FeeAboveMaximum { .. }));
+
+    let mut params = app.market_params();
+    params.fee_schedule = vec![point(36, "1"), point(12, "3")];
+    app.add_market(&app.admin, params, 1_000).unwrap();
+    app.id = MarketId(2);
+
+    let simulate = |app: &Predict| {
+        app.query::<SimulateDepositResp>(&QueryMsg::SimulateDeposit {
+            id: app.id,
+            outcome: 0.into(),
+            amount: Collateral(1_000u16.into()),
+            liquidity: Decimal256::zero(),
+            referred: false,
+        })
+        .unwrap()
+    };
+    let advance = |app: &Predict, seconds: u64| {
+        app.app
+            .borrow_mut()
+            .update_block(|b| b.time = b.time.plus_seconds(seconds))
+    };
 
-    app.set_winner(&app.arbitrator, 0).unwrap();
-    let better_after = app.query_balance(&app.better).unwrap();
-    assert_eq!(better_before, better_after);
+    // Flat before the first point
+    let simulated = simulate(&app);
+    assert_eq!(simulated.fee_rate, Decimal256::percent(1));
+    assert_eq!(simulated.fee, Collateral(10u8.into()));
+    let withdraw: SimulateWithdrawResp = app
+        .query(&QueryMsg::SimulateWithdraw {
+            id: app.id,
+            outcome: 0.into(),
+            tokens: Token(100u8.into()),
+        })
+        .unwrap();
+    assert_eq!(withdraw.fee_rate, Decimal256::percent(2));
 
-    app.collect(&app.house).unwrap();
-    let house_after = app.query_balance(&app.house).unwrap();
-    assert_eq!(Uint128::from(2000u16), house_after);
+    // Halfway between the points
+    advance(&app, hours(24));
+    let simulated = simulate(&app);
+    assert_eq!(simulated.fee_rate, Decimal256::percent(2));
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.stats.deposit_fees, Collateral(20u8.into()));
+
+    // Held at the last point
+    advance(&app, hours(18));
+    assert_eq!(simulate(&app).fee_rate, Decimal256::percent(3));
+
+    // Markets without a schedule are unaffected
+    app.id = MarketId(1);
+    assert_eq!(simulate(&app).fee_rate, Decimal256::percent(1));
 }
 
 #[test]
-fn withdrawal_leaves_money() {
-    let app = Predict::new();
+fn oracle_resolution() {
+    const ORACLE_PRICE: Item<Price> = Item::new("price");
+    fn oracle_execute(deps: DepsMut, _: Env, _: MessageInfo, price: Price) -> StdResult<Response> {
+        ORACLE_PRICE.save(deps.storage, &price)?;
+        Ok(Response::new())
+    }
+    fn oracle_instantiate(_: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+    fn oracle_query(deps: Deps, _: Env, msg: OracleQueryMsg) -> StdResult<Binary> {
+        let OracleQueryMsg::PriceFeed { id } = msg;
+        to_json_binary(&PriceFeedResponse {
+            price_feed: PriceFeed {
+                id,
+                price: ORACLE_PRICE.load(deps.storage)?,
+            },
+        })
+    }
 
-    let bet_amount = 1_000u64;
-    // Arbitrator doesn't have any funds
-    app.place_bet(&app.arbitrator, 1, bet_amount).unwrap_err();
+    let mut app = Predict::new();
+    let oracle = {
+        let mut inner = app.app.borrow_mut();
+        let code_id = inner.store_code(Box::new(ContractWrapper::new(
+            oracle_execute,
+            oracle_instantiate,
+            oracle_query,
+        )));
+        inner
+            .instantiate_contract(code_id, app.admin.clone(), &Empty {}, &[], "oracle", None)
+            .unwrap()
+    };
+    let set_price = |app: &Predict, price: i64, conf: u64, publish_time: Timestamp| {
+        app.app
+            .borrow_mut()
+            .execute_contract(
+                app.admin.clone(),
+                oracle.clone(),
+                &Price {
+                    price: price.into(),
+                    conf: conf.into(),
+                    expo: -2,
+                    publish_time: publish_time.seconds().try_into().unwrap(),
+                },
+                &[],
+            )
+            .unwrap();
+    };
+    let resolve = |app: &Predict| {
+        app.execute(&app.better, &ExecuteMsg::Resolve { id: app.id }, None)
+            .map_err(|err| err.downcast::<Error>().unwrap())
+    };
 
-    let better_before = app.query_balance(&app.better).unwrap();
-    let tokens1 = app.query_tokens(&app.better, 1).unwrap();
-    assert_eq!(tokens1, Token::zero());
-    app.place_bet(&app.better, 1, bet_amount).unwrap();
-    let tokens2 = app.query_tokens(&app.better, 1).unwrap();
-    assert_ne!(tokens2, Token::zero());
-    app.withdraw(&app.better, 1, tokens2 + tokens2).unwrap_err();
-    app.withdraw(&app.better, 1, tokens2).unwrap();
-    let tokens3 = app.query_tokens(&app.better, 1).unwrap();
-    assert!(tokens3 <= Token(Uint256::from(1u8)));
-    let better_after = app.query_balance(&app.better).unwrap();
+    let params = app.market_params();
+    let resolution_time = params.deposit_stop_date;
+    let source = ResolutionSource {
+        oracle: oracle.to_string(),
+        feed_id: "0xb00b60f88b03a6a625a8d1c048c3f66653edf217439983d037e7222c4e612819".to_owned(),
+        strike: "10".parse().unwrap(),
+        comparator: Comparator::Above,
+        resolution_time,
+        max_publish_delay_seconds: 60,
+        max_confidence_ratio: Decimal256::percent(1),
+    };
+    let with_resolution = |source: ResolutionSource| AddMarketParams {
+        resolution: Some(source),
+        ..params.clone()
+    };
 
-    // Make sure we left money behind for fees
-    assert!(better_before > better_after);
+    let invalid_source = |source: ResolutionSource| {
+        let err = app
+            .add_market(&app.admin, with_resolution(source), 1_000)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast::<Error>().unwrap(),
+            Error::InvalidResolutionSource { .. }
+        ));
+    };
+    // Only oracles listed in the config can be used
+    invalid_source(source.clone());
+    let mut config = test_config();
+    config.oracles.push(oracle.to_string());
+    app.execute(&app.admin, &ExecuteMsg::UpdateConfig { config }, None)
+        .unwrap();
+
+    let mut invalid = with_resolution(source.clone());
+    invalid.outcomes.push(invalid.outcomes[0].clone());
+    app.add_market(&app.admin, invalid, 1_000).unwrap_err();
+    let invalid = with_resolution(ResolutionSource {
+        resolution_time: resolution_time.minus_seconds(1),
+        ..source.clone()
+    });
+    app.add_market(&app.admin, invalid, 1_000).unwrap_err();
+    let invalid = with_resolution(ResolutionSource {
+        oracle: "NOT-AN-ADDRESS".to_owned(),
+        ..source.clone()
+    });
+    app.add_market(&app.admin, invalid, 1_000).unwrap_err();
+    invalid_source(ResolutionSource {
+        feed_id: "atom-usd".to_owned(),
+        ..source.clone()
+    });
+    // Sources can't be looser than the configured limits
+    let config = test_config();
+    invalid_source(ResolutionSource {
+        max_publish_delay_seconds: config.max_publish_delay_seconds + 1,
+        ..source.clone()
+    });
+    invalid_source(ResolutionSource {
+        max_confidence_ratio: config.max_confidence_ratio + Decimal256::permille(1),
+        ..source.clone()
+    });
+
+    app.add_market(&app.admin, with_resolution(source.clone()), 1_000)
+        .unwrap();
+    let strike_above_price = ResolutionSource {
+        strike: "11".parse().unwrap(),
+        ..source
+    };
+    app.add_market(&app.admin, with_resolution(strike_above_price), 1_000)
+        .unwrap();
+    app.id = MarketId(2);
+
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::ResolutionTimeNotReached { .. }
+    ));
+    app.jump_days(2);
+
+    set_price(&app, 1_050, 1, resolution_time.minus_seconds(1));
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::PriceOutsidePublishWindow { .. }
+    ));
+    set_price(&app, 1_050, 1, resolution_time.plus_seconds(61));
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::PriceOutsidePublishWindow { .. }
+    ));
+    set_price(&app, 1_050, 50, resolution_time.plus_seconds(30));
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::PriceConfidenceTooWide { .. }
+    ));
+    set_price(&app, -1_050, 1, resolution_time.plus_seconds(30));
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::InvalidOraclePrice { .. }
+    ));
+
+    // 10.50 is above the strike of 10
+    set_price(&app, 1_050, 5, resolution_time.plus_seconds(30));
+    resolve(&app).unwrap();
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::WinnerAlreadyProposed { .. }
+    ));
+    app.finalize_winner().unwrap();
+    assert_eq!(
+        app.query_latest_market().unwrap().winner,
+        Some(OutcomeId(0))
+    );
+
+    // 10.50 is below the strike of 11
+    app.id = MarketId(3);
+    resolve(&app).unwrap();
+    app.finalize_winner().unwrap();
+    assert_eq!(
+        app.query_latest_market().unwrap().winner,
+        Some(OutcomeId(1))
+    );
+
+    app.id = MarketId(1);
+    assert!(matches!(
+        resolve(&app).unwrap_err(),
+        Error::NoResolutionSource { .. }
+    ));
+}
+
+#[test]
+fn scalar_market() {
+    let mut app = Predict::new();
+    let range = ScalarRange {
+        min: "-10".parse().unwrap(),
+        max: "30".parse().unwrap(),
+    };
+    let propose = |app: &Predict, value: &str| {
+        app.execute(
+            &app.arbitrator,
+            &ExecuteMsg::ProposeScalarResult {
+                id: app.id,
+                value: value.parse().unwrap(),
+            },
+            None,
+        )
+        .map_err(|err| err.downcast::<Error>().unwrap())
+    };
+
+    let mut params = app.market_params();
+    params.scalar = Some(ScalarRange {
+        min: range.max,
+        max: range.min,
+    });
+    app.add_market(&app.admin, params, 1_000).unwrap_err();
+    let mut params = app.market_params();
+    params.scalar = Some(range.clone());
+    params.outcomes.push(params.outcomes[0].clone());
+    app.add_market(&app.admin, params, 1_000).unwrap_err();
+    // Payouts divide by max - min, so the width must be representable
+    let mut params = app.market_params();
+    params.scalar = Some(ScalarRange {
+        min: SignedDecimal256::MIN,
+        max: SignedDecimal256::MAX,
+    });
+    let err = app.add_market(&app.admin, params, 1_000).unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::InvalidScalarRange { .. }
+    ));
+
+    let mut params = app.market_params();
+    params.scalar = Some(range.clone());
+    app.add_market(&app.admin, params.clone(), 1_000).unwrap();
+    params.dispute_period_seconds = 60 * 60 * 24;
+    app.add_market(&app.admin, params, 1_000).unwrap();
+    app.id = MarketId(2);
 
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let long = app.query_tokens(&app.better, 0).unwrap();
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+    let short = app.query_tokens(&app.better, 1).unwrap();
+    app.id = MarketId(3);
+    app.place_bet(&app.better, 0, 1_000).unwrap();
     app.jump_days(3);
-    app.set_winner(&app.arbitrator, 0).unwrap();
-    let better_final = app.query_balance(&app.better).unwrap();
 
-    // No change in better balance because he lost the bet (and also
-    // we didn't collect yet)
-    assert_eq!(better_after, better_final);
+    app.id = MarketId(1);
+    assert!(matches!(
+        propose(&app, "20").unwrap_err(),
+        Error::NotScalarMarket { .. }
+    ));
+    app.id = MarketId(2);
+    let err = app
+        .propose_winner(&app.arbitrator, 0)
+        .unwrap_err()
+        .downcast::<Error>()
+        .unwrap();
+    assert!(matches!(err, Error::ScalarMarket { .. }));
+
+    // LONG pays (20 - -10) / (30 - -10) = 0.75, SHORT pays the remaining 0.25
+    propose(&app, "20").unwrap();
+    app.collect(&app.better).unwrap_err();
+    app.finalize_winner().unwrap();
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.scalar_result, Some("20".parse().unwrap()));
+    assert_eq!(market.winner, None);
+    let before = app.query_balance(&app.better).unwrap();
+    app.collect(&app.better).unwrap();
+    let expected = Collateral(long.0) * Decimal256::percent(75)
+        + Collateral(short.0) * Decimal256::percent(25);
+    assert_eq!(
+        app.query_balance(&app.better).unwrap() - before,
+        Uint128::try_from(expected.0).unwrap()
+    );
+    app.collect(&app.house).unwrap();
+
+    // Results are clamped to the range, and can be overridden during the dispute period
+    app.id = MarketId(3);
+    propose(&app, "100").unwrap();
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::OverrideScalarResult {
+            id: app.id,
+            value: "-50".parse().unwrap(),
+        },
+        None,
+    )
+    .unwrap();
+    let err = app
+        .collect(&app.better)
+        .unwrap_err()
+        .downcast::<Error>()
+        .unwrap();
+    assert!(matches!(err, Error::NothingToCollect { .. }));
+    let before = app.query_balance(&app.house).unwrap();
+    app.collect(&app.house).unwrap();
+    assert!(app.query_balance(&app.house).unwrap() > before);
+}
+
+#[test]
+fn market_config_limits() {
+    let app = Predict::new();
+    let mut config = test_config();
+    config.collateral[0].min_liquidity = 500u16.into();
+    config.collateral[0].max_liquidity = 5_000u16.into();
+    config.min_duration_seconds = 60 * 60 * 36;
+    config.max_duration_seconds = 60 * 60 * 24 * 7;
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::UpdateConfig {
+            config: config.clone(),
+        },
+        None,
+    )
+    .unwrap();
+
+    let err = |params: AddMarketParams, funds: u64| {
+        app.add_market(&app.admin, params, funds)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+    };
+
+    let mut params = app.market_params();
+    params.deposit_fee = "0.5".parse().unwrap();
+    assert!(matches!(err(params, 1_000), Error::FeeAboveMaximum { .. }));
+    let mut params = app.market_params();
+    params.withdrawal_fee = Decimal256::one();
+    assert!(matches!(err(params, 1_000), Error::FeeAboveMaximum { .. }));
+    let mut params = app.market_params();
+    params.denom = Denom::Native("other".to_owned());
+    assert!(matches!(
+        err(params, 1_000),
+        Error::CollateralNotAllowed { .. } | Error::IncorrectFundsDenom { .. }
+    ));
+    assert!(matches!(
+        err(app.market_params(), 400),
+        Error::LiquidityBelowMinimum { .. }
+    ));
+    assert!(matches!(
+        err(app.market_params(), 6_000),
+        Error::LiquidityAboveMaximum { .. }
+    ));
+    let mut params = app.market_params();
+    params.deposit_stop_date = params.withdrawal_stop_date;
+    assert!(matches!(err(params, 1_000), Error::DurationTooShort { .. }));
+    let mut params = app.market_params();
+    params.deposit_stop_date = params.deposit_stop_date.plus_days(7);
+    assert!(matches!(err(params, 1_000), Error::DurationTooLong { .. }));
+
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+
+    // Invalid configs are rejected
+    let update =
+        |config: Config| app.execute(&app.admin, &ExecuteMsg::UpdateConfig { config }, None);
+    let mut bad = config.clone();
+    bad.max_deposit_fee = Decimal256::one();
+    update(bad).unwrap_err();
+    let mut bad = config.clone();
+    bad.min_duration_seconds = bad.max_duration_seconds + 1;
+    update(bad).unwrap_err();
+    let mut bad = config.clone();
+    bad.collateral[0].min_liquidity = Uint128::zero();
+    update(bad).unwrap_err();
+    let mut bad = config.clone();
+    bad.collateral.push(bad.collateral[0].clone());
+    update(bad).unwrap_err();
+}
+
+#[test]
+fn permissionless_markets() {
+    let mut app = Predict::new();
+    let mut config = test_config();
+    config.permissionless = true;
+    config.collateral[0].creator_bond = 500u16.into();
+    config.max_deposit_fee = "0.05".parse().unwrap();
+    config.max_outcomes = 2;
+    let update_config = |app: &Predict, sender: &Addr, config: &Config| {
+        app.execute(
+            sender,
+            &ExecuteMsg::UpdateConfig {
+                config: config.clone(),
+            },
+            None,
+        )
+    };
+
+    // Only the admin can enable permissionless markets, with sane limits
+    update_config(&app, &app.better, &config).unwrap_err();
+    let mut bad_config = config.clone();
+    bad_config.max_outcomes = 100;
+    update_config(&app, &app.admin, &bad_config).unwrap_err();
+    update_config(&app, &app.admin, &config).unwrap();
+    assert_eq!(
+        app.query::<Config>(&QueryMsg::Config {})
+            .unwrap()
+            .max_outcomes,
+        2
+    );
+
+    // Markets must respect the config and include the bond
+    let bonded_params = |app: &Predict| AddMarketParams {
+        dispute_period_seconds: 60 * 60 * 24,
+        ..app.market_params()
+    };
+    let mut params = bonded_params(&app);
+    params.deposit_fee = "0.1".parse().unwrap();
+    app.add_market(&app.better, params, 1_500).unwrap_err();
+    let mut params = bonded_params(&app);
+    params.outcomes.push(OutcomeDef {
+        label: "Maybe".to_owned(),
+        initial_amount: Token(100u16.into()),
+    });
+    app.add_market(&app.better, params, 1_500).unwrap_err();
+    app.add_market(&app.better, bonded_params(&app), 500)
+        .unwrap_err();
+    let err = app
+        .add_market(&app.better, app.market_params(), 1_500)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::DisputePeriodTooShort {
+            dispute_period_seconds: 0,
+            min: 86_400
+        }
+    ));
+
+    let before = app.query_balance(&app.better).unwrap();
+    app.add_market(&app.better, bonded_params(&app), 1_500)
+        .unwrap();
+    assert_eq!(
+        before - app.query_balance(&app.better).unwrap(),
+        Uint128::new(1_500)
+    );
+    app.id = MarketId(2);
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.pool_size, Collateral(1_000u16.into()));
+    let bond = market.creator_bond.unwrap();
+    assert_eq!(bond.creator, app.better);
+    assert_eq!(bond.amount, Collateral(500u16.into()));
+
+    // The bond is returned once the winner is final
+    app.jump_days(3);
+    let before = app.query_balance(&app.better).unwrap();
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    app.jump_days(1);
+    app.finalize_winner().unwrap();
+    assert_eq!(
+        app.query_balance(&app.better).unwrap() - before,
+        Uint128::new(500)
+    );
+    assert!(app.query_latest_market().unwrap().creator_bond.is_none());
+
+    // Voiding as invalid slashes the bond to the admin
+    app.add_market(&app.better, bonded_params(&app), 1_500)
+        .unwrap();
+    app.id = MarketId(3);
+    let void_invalid = |app: &Predict, sender: &Addr| {
+        app.execute(
+            sender,
+            &ExecuteMsg::VoidMarket {
+                id: app.id,
+                invalid: true,
+            },
+            None,
+        )
+    };
+    void_invalid(&app, &app.arbitrator).unwrap_err();
+    let before = app.query_balance(&app.admin).unwrap();
+    void_invalid(&app, &app.admin).unwrap();
+    assert_eq!(
+        app.query_balance(&app.admin).unwrap() - before,
+        Uint128::new(500)
+    );
+
+    // A creator acting as their own arbitrator cannot void to recover the bond
+    let mut params = bonded_params(&app);
+    params.arbitrator = app.better.to_string();
+    app.add_market(&app.better, params, 1_500).unwrap();
+    app.id = MarketId(4);
+    let before = app.query_balance(&app.better).unwrap();
+    app.void_market(&app.better).unwrap();
+    assert_eq!(app.query_balance(&app.better).unwrap(), before);
+    assert!(app.query_latest_market().unwrap().creator_bond.is_some());
+    void_invalid(&app, &app.better).unwrap_err();
+    let before = app.query_balance(&app.admin).unwrap();
+    void_invalid(&app, &app.admin).unwrap();
+    assert_eq!(
+        app.query_balance(&app.admin).unwrap() - before,
+        Uint128::new(500)
+    );
+    assert!(app.query_latest_market().unwrap().creator_bond.is_none());
+    app.void_market(&app.admin).unwrap_err();
+
+    // A creator arbitrating their own market cannot propose and finalize
+    // in the same block, so the admin can still step in
+    let mut params = bonded_params(&app);
+    params.arbitrator = app.better.to_string();
+    app.add_market(&app.better, params, 1_500).unwrap();
+    app.id = MarketId(5);
+    app.jump_days(2);
+    app.propose_winner(&app.better, 0).unwrap();
+    let err = app.finalize_winner().unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::DisputePeriodActive { .. }
+    ));
+    assert!(app.query_latest_market().unwrap().creator_bond.is_some());
+    let before = app.query_balance(&app.admin).unwrap();
+    void_invalid(&app, &app.admin).unwrap();
+    assert_eq!(
+        app.query_balance(&app.admin).unwrap() - before,
+        Uint128::new(500)
+    );
+
+    // Permissionless markets need a nonzero minimum dispute period
+    let mut bad_config = config.clone();
+    bad_config.min_dispute_period_seconds = 0;
+    update_config(&app, &app.admin, &bad_config).unwrap_err();
+
+    // Disabling permissionless mode stops new markets, but not the admin
+    update_config(&app, &app.admin, &test_config()).unwrap();
+    app.add_market(&app.better, bonded_params(&app), 1_500)
+        .unwrap_err();
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+    app.id = MarketId(6);
+    assert!(app.query_latest_market().unwrap().creator_bond.is_none());
+}
+
+#[test]
+fn sanity() {
+    let app = Predict::new();
+    app.jump_days(3);
+    let amount_before = app.query_balance(&app.house).unwrap();
+    assert_eq!(Uint128::from(0u16), amount_before);
+
+    // Admin cannot set the winner
+    app.set_winner(&app.admin, 0).unwrap_err();
+
+    // Arbitrator can set the winner
+    app.set_winner(&app.arbitrator, 0).unwrap();
+
+    // House can claim its winnings
+    app.collect(&app.house).unwrap();
+    app.collect(&app.house).unwrap_err();
+
+    let amount_after = app.query_balance(&app.house).unwrap();
+    assert_eq!(Uint128::from(1000u16), amount_after);
+}
+
+#[test]
+fn losing_bet() {
+    let app = Predict::new();
+
+    // Arbitrator doesn't have any funds
+    app.place_bet(&app.arbitrator, 1, 1_000).unwrap_err();
+
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+
+    app.jump_days(3);
+    let better_before = app.query_balance(&app.better).unwrap();
+
+    let house_before = app.query_balance(&app.house).unwrap();
+    assert_eq!(house_before, Uint128::from(0u16));
+
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    let better_after = app.query_balance(&app.better).unwrap();
+    assert_eq!(better_before, better_after);
+
+    app.collect(&app.house).unwrap();
+    let house_after = app.query_balance(&app.house).unwrap();
+    assert_eq!(Uint128::from(2000u16), house_after);
+}
+
+#[test]
+fn withdrawal_leaves_money() {
+    let app = Predict::new();
+
+    let bet_amount = 1_000u64;
+    // Arbitrator doesn't have any funds
+    app.place_bet(&app.arbitrator, 1, bet_amount).unwrap_err();
+
+    let better_before = app.query_balance(&app.better).unwrap();
+    let tokens1 = app.query_tokens(&app.better, 1).unwrap();
+    assert_eq!(tokens1, Token::zero());
+    app.place_bet(&app.better, 1, bet_amount).unwrap();
+    let tokens2 = app.query_tokens(&app.better, 1).unwrap();
+    assert_ne!(tokens2, Token::zero());
+    app.withdraw(&app.better, 1, tokens2 + tokens2).unwrap_err();
+    app.withdraw(&app.better, 1, tokens2).unwrap();
+    let tokens3 = app.query_tokens(&app.better, 1).unwrap();
+    assert!(tokens3 <= Token(Uint256::from(1u8)));
+    let better_after = app.query_balance(&app.better).unwrap();
+
+    // Make sure we left money behind for fees
+    assert!(better_before > better_after);
+
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    let better_final = app.query_balance(&app.better).unwrap();
+
+    // No change in better balance because he lost the bet (and also
+    // we didn't collect yet)
+    assert_eq!(better_after, better_final);
+
+    // We try to collect and fail since we do not have any winning
+    // outcome tokens
+    app.collect(&app.better).unwrap_err();
+
+    app.collect(&app.house).unwrap();
+    let house_after = app.query_balance(&app.house).unwrap();
+    assert_eq!(
+        Uint128::from(1000u16) + better_before - better_after,
+        house_after
+    );
+}
+
+#[test]
+fn winning_bet() {
+    let app = Predict::new();
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+
+    app.jump_days(3);
+
+    let better_before = app.query_balance(&app.better).unwrap();
+    app.set_winner(&app.arbitrator, 0).unwrap();
+
+    app.collect(&app.better).unwrap();
+    app.collect(&app.better).unwrap_err();
+    app.collect(&app.arbitrator).unwrap_err();
+    let better_after = app.query_balance(&app.better).unwrap();
+
+    assert!(better_before < better_after);
+
+    let house_after = app.query_balance(&app.house).unwrap();
+    assert!(Uint128::from(1000u16) > house_after);
+}
+
+#[test]
+fn void_market_refunds() {
+    let app = Predict::new();
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let better_before = app.query_balance(&app.better).unwrap();
+
+    // Only the admin or arbitrator can void
+    app.void_market(&app.better).unwrap_err();
+    app.void_market(&app.arbitrator).unwrap();
+    app.void_market(&app.admin).unwrap_err();
+
+    let market = app.query_latest_market().unwrap();
+    let prices = market.void_prices.clone().unwrap();
+    assert_eq!(prices.len(), 2);
+
+    // Trading and resolution are closed
+    app.place_bet(&app.better, 0, 1_000).unwrap_err();
+    app.withdraw(&app.better, 0, app.query_tokens(&app.better, 0).unwrap())
+        .unwrap_err();
+    app.provide(&app.better, 1_000).unwrap_err();
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 0).unwrap_err();
+
+    // Refunds are paid according to the void prices
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    app.collect(&app.better).unwrap();
+    app.collect(&app.better).unwrap_err();
+    app.collect(&app.arbitrator).unwrap_err();
+    let better_after = app.query_balance(&app.better).unwrap();
+    let refund = Uint256::from(better_after - better_before);
+    assert_eq!(refund, tokens.0 * prices[0]);
+    assert!(refund > Uint256::zero());
+
+    app.collect(&app.house).unwrap();
+    let house_after = app.query_balance(&app.house).unwrap();
+    assert!(Uint256::from(house_after) + refund <= market.pool_size.0);
+}
+
+#[test]
+fn cannot_void_resolved_market() {
+    let app = Predict::new();
+
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    app.void_market(&app.admin).unwrap_err();
+}
+
+fn disputed_market() -> Predict {
+    let mut app = Predict::new();
+    let mut params = app.market_params();
+    params.dispute_period_seconds = 60 * 60 * 24;
+    app.add_market(&app.admin, params, 1_000).unwrap();
+    app.id = MarketId(2);
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.jump_days(3);
+    app
+}
+
+#[test]
+fn dispute_period_finalize() {
+    let app = disputed_market();
+
+    app.finalize_winner().unwrap_err();
+    app.propose_winner(&app.better, 0).unwrap_err();
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    app.propose_winner(&app.arbitrator, 1).unwrap_err();
+
+    // Cannot collect or finalize during the dispute period
+    app.collect(&app.better).unwrap_err();
+    app.finalize_winner().unwrap_err();
+
+    app.jump_days(1);
+    app.finalize_winner().unwrap();
+    app.finalize_winner().unwrap_err();
+    app.collect(&app.better).unwrap();
+}
+
+#[test]
+fn dispute_period_override() {
+    let app = disputed_market();
+
+    let override_winner = |sender: &Addr, outcome: u8| {
+        app.execute(
+            sender,
+            &ExecuteMsg::OverrideWinner {
+                id: app.id,
+                outcome: outcome.into(),
+            },
+            None,
+        )
+    };
+
+    // Nothing to override yet
+    override_winner(&app.admin, 1).unwrap_err();
+
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    override_winner(&app.arbitrator, 1).unwrap_err();
+    override_winner(&app.admin, 1).unwrap();
+    assert_eq!(app.query_latest_market().unwrap().winner, Some(1.into()));
+
+    // Override is final
+    app.finalize_winner().unwrap_err();
+    app.collect(&app.better).unwrap_err();
+    app.collect(&app.house).unwrap();
+}
+
+#[test]
+fn dispute_period_void() {
+    let app = disputed_market();
+
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+
+    // Arbitrator cannot void its own proposal, but the admin can
+    app.void_market(&app.arbitrator).unwrap_err();
+    app.void_market(&app.admin).unwrap();
+
+    app.jump_days(1);
+    app.finalize_winner().unwrap_err();
+    app.collect(&app.better).unwrap();
+}
+
+#[test]
+fn deprecated_set_winner() {
+    let app = Predict::new();
+
+    // Older clients don't send a dispute period
+    let params = cosmwasm_std::to_json_string(&app.market_params()).unwrap();
+    let params = params.replace(r#""dispute_period_seconds":0,"#, "");
+    assert!(!params.contains("dispute_period_seconds"));
+    let params: AddMarketParams = cosmwasm_std::from_json(params).unwrap();
+    assert_eq!(params.dispute_period_seconds, 0);
+
+    app.jump_days(3);
+    let set_winner = ExecuteMsg::SetWinner {
+        id: app.id,
+        outcome: 1.into(),
+    };
+    app.execute(&app.better, &set_winner, None).unwrap_err();
+    app.execute(&app.arbitrator, &set_winner, None).unwrap();
+    let proposed = app.query_latest_market().unwrap().proposed_winner.unwrap();
+    assert_eq!(proposed.outcome, Some(OutcomeId(1)));
+    app.finalize_winner().unwrap();
+    assert_eq!(
+        app.query_latest_market().unwrap().winner,
+        Some(OutcomeId(1))
+    );
+}
+
+#[test]
+fn dispute_period_closed() {
+    let app = disputed_market();
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    app.jump_days(2);
+
+    // Once the dispute period is over, the proposal can only be finalized
+    let err = app
+        .execute(
+            &app.admin,
+            &ExecuteMsg::OverrideWinner {
+                id: app.id,
+                outcome: 1.into(),
+            },
+            None,
+        )
+        .unwrap_err()
+        .downcast::<Error>()
+        .unwrap();
+    assert!(matches!(err, Error::DisputePeriodOver { .. }));
+    let err = app
+        .void_market(&app.admin)
+        .unwrap_err()
+        .downcast::<Error>()
+        .unwrap();
+    assert!(matches!(err, Error::DisputePeriodOver { .. }));
+
+    app.finalize_winner().unwrap();
+    app.collect(&app.better).unwrap();
+}
+
+#[test]
+fn mint_and_redeem_set() {
+    let app = Predict::new();
+    let prices_before = app
+        .query::<PricesResp>(&QueryMsg::Prices { id: app.id })
+        .unwrap();
+    let balance_before = app.query_balance(&app.better).unwrap();
+
+    app.execute(&app.better, &ExecuteMsg::MintSet { id: app.id }, Some(500))
+        .unwrap();
+    assert_eq!(
+        app.query_tokens(&app.better, 0).unwrap(),
+        Token(500u16.into())
+    );
+    assert_eq!(
+        app.query_tokens(&app.better, 1).unwrap(),
+        Token(500u16.into())
+    );
+    assert_eq!(app.query_wallet_count().unwrap(), (2, vec![2, 2]));
+
+    // Pool and prices are untouched
+    let prices_after = app
+        .query::<PricesResp>(&QueryMsg::Prices { id: app.id })
+        .unwrap();
+    assert_eq!(
+        prices_before.outcomes[0].price,
+        prices_after.outcomes[0].price
+    );
+    assert_eq!(
+        prices_before.pool_size.0 + Uint256::from(500u16),
+        prices_after.pool_size.0
+    );
+
+    let redeem = |amount: u16| {
+        app.execute(
+            &app.better,
+            &ExecuteMsg::RedeemSet {
+                id: app.id,
+                amount: Token(amount.into()),
+            },
+            None,
+        )
+    };
+    redeem(0).unwrap_err();
+    redeem(501).unwrap_err();
+    redeem(200).unwrap();
+    assert_eq!(
+        app.query_tokens(&app.better, 0).unwrap(),
+        Token(300u16.into())
+    );
+
+    // An unbalanced position can only redeem complete sets
+    app.withdraw(&app.better, 0, Token(300u16.into())).unwrap();
+    redeem(1).unwrap_err();
+    app.place_bet(&app.better, 0, 400).unwrap();
+    redeem(300).unwrap();
+    assert_eq!(app.query_tokens(&app.better, 1).unwrap(), Token::zero());
+
+    // Minting closes with deposits, redeeming once a winner is proposed
+    app.jump_days(3);
+    app.execute(&app.better, &ExecuteMsg::MintSet { id: app.id }, Some(500))
+        .unwrap_err();
+    app.execute(&app.better, &ExecuteMsg::MintSet { id: app.id }, None)
+        .unwrap_err();
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    redeem(1).unwrap_err();
+
+    assert!(app.query_balance(&app.better).unwrap() < balance_before);
+}
+
+#[test]
+fn transfer_positions() {
+    let app = Predict::new();
+    let recipient = Addr::unchecked("recipient");
+
+    let transfer_tokens = |sender: &Addr, recipient: &Addr, amount: Token| {
+        app.execute(
+            sender,
+            &ExecuteMsg::TransferTokens {
+                id: app.id,
+                outcome: 0.into(),
+                amount,
+                recipient: recipient.to_string(),
+            },
+            None,
+        )
+    };
+    let transfer_shares = |sender: &Addr, recipient: &Addr, shares: LpShare| {
+        app.execute(
+            sender,
+            &ExecuteMsg::TransferShares {
+                id: app.id,
+                shares,
+                recipient: recipient.to_string(),
+            },
+            None,
+        )
+    };
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    assert_eq!(app.query_wallet_count().unwrap(), (2, vec![2, 1]));
+
+    transfer_tokens(&app.better, &recipient, Token::zero()).unwrap_err();
+    transfer_tokens(&app.better, &recipient, tokens + Token(1u8.into())).unwrap_err();
+    transfer_tokens(&app.better, &app.better, tokens).unwrap_err();
+
+    // Partial transfer adds a wallet, full transfer removes the sender
+    let half = Token(tokens.0 / Uint256::from(2u8));
+    transfer_tokens(&app.better, &recipient, half).unwrap();
+    assert_eq!(app.query_wallet_count().unwrap(), (3, vec![3, 1]));
+    transfer_tokens(&app.better, &recipient, tokens - half).unwrap();
+    assert_eq!(app.query_wallet_count().unwrap(), (2, vec![2, 1]));
+    assert_eq!(app.query_tokens(&recipient, 0).unwrap(), tokens);
+    assert_eq!(app.query_tokens(&app.better, 0).unwrap(), Token::zero());
+
+    // The house cannot give away all of its shares
+    let house_shares = app.query_holder(&app.house).unwrap().shares;
+    transfer_shares(&app.house, &recipient, house_shares).unwrap_err();
+    transfer_shares(&app.better, &recipient, house_shares).unwrap_err();
+    let shares = LpShare(house_shares.0 / Uint256::from(2u8));
+    transfer_shares(&app.house, &app.better, shares).unwrap();
+    assert_eq!(app.query_wallet_count().unwrap(), (3, vec![2, 1]));
+    assert_eq!(app.query_latest_market().unwrap().lp_wallets, 2);
+    assert_eq!(app.query_holder(&app.better).unwrap().shares, shares);
+
+    // Transfers stop once a winner is proposed
+    app.jump_days(3);
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    transfer_tokens(&recipient, &app.better, tokens).unwrap_err();
+    transfer_shares(&app.better, &recipient, shares).unwrap_err();
+}
+
+#[test]
+fn cw1155_balances_and_approvals() {
+    let app = Predict::new();
+    let operator = Addr::unchecked("operator");
+    let recipient = Addr::unchecked("recipient");
+    let token_id = |outcome: u8| {
+        OutcomeToken {
+            market: app.id,
+            outcome: outcome.into(),
+        }
+        .to_string()
+    };
+    let balance = |owner: &Addr, outcome: u8| {
+        app.query::<BalanceResp>(&QueryMsg::Balance {
+            owner: owner.to_string(),
+            token_id: token_id(outcome),
+        })
+        .unwrap()
+        .balance
+    };
+    let send_from = |sender: &Addr, value: u64| {
+        app.execute(
+            sender,
+            &ExecuteMsg::SendFrom {
+                from: app.better.to_string(),
+                to: recipient.to_string(),
+                token_id: token_id(0),
+                value: value.into(),
+                msg: None,
+            },
+            None,
+        )
+    };
+
+    assert_eq!(token_id(1), "1/1");
+    assert_eq!(
+        OutcomeToken::from_str("3/2").unwrap(),
+        OutcomeToken {
+            market: MarketId(3),
+            outcome: 2.into()
+        }
+    );
+    OutcomeToken::from_str("3").unwrap_err();
+    OutcomeToken::from_str("3/x").unwrap_err();
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    assert_eq!(Uint256::from(balance(&app.better, 0)), tokens.0);
+    assert_eq!(balance(&recipient, 0), Uint128::zero());
+    app.query::<BalanceResp>(&QueryMsg::Balance {
+        owner: app.better.to_string(),
+        token_id: token_id(2),
+    })
+    .unwrap_err();
+
+    let balances = app
+        .query::<BatchBalanceResp>(&QueryMsg::BatchBalance {
+            owner: app.house.to_string(),
+            token_ids: vec![token_id(0), token_id(1)],
+        })
+        .unwrap()
+        .balances;
+    assert_eq!(
+        balances,
+        vec![balance(&app.house, 0), balance(&app.house, 1)]
+    );
+
+    // Sending to yourself is a no-op, but still checks the balance
+    let send_to_self = |value: Uint256| {
+        app.execute(
+            &app.better,
+            &ExecuteMsg::SendFrom {
+                from: app.better.to_string(),
+                to: app.better.to_string(),
+                token_id: token_id(0),
+                value: value.try_into().unwrap(),
+                msg: None,
+            },
+            None,
+        )
+    };
+    send_to_self(tokens.0).unwrap();
+    assert_eq!(app.query_tokens(&app.better, 0).unwrap(), tokens);
+    send_to_self(tokens.0 + Uint256::one()).unwrap_err();
+    app.execute(
+        &app.better,
+        &ExecuteMsg::TransferTokens {
+            id: app.id,
+            outcome: 0.into(),
+            amount: tokens,
+            recipient: app.better.to_string(),
+        },
+        None,
+    )
+    .unwrap_err();
+
+    // Operators need approval, and approvals expire
+    send_from(&operator, 100).unwrap_err();
+    let now = app.app.borrow().block_info().time;
+    app.execute(
+        &app.better,
+        &ExecuteMsg::ApproveAll {
+            operator: operator.to_string(),
+            expires: Some(Expiration::AtTime(now)),
+        },
+        None,
+    )
+    .unwrap_err();
+    app.execute(
+        &app.better,
+        &ExecuteMsg::ApproveAll {
+            operator: operator.to_string(),
+            expires: Some(Expiration::AtTime(now.plus_days(1))),
+        },
+        None,
+    )
+    .unwrap();
+    let is_approved = || {
+        app.query::<IsApprovedForAllResp>(&QueryMsg::IsApprovedForAll {
+            owner: app.better.to_string(),
+            operator: operator.to_string(),
+        })
+        .unwrap()
+        .approved
+    };
+    assert!(is_approved());
+    let operators = app
+        .query::<ApprovedForAllResp>(&QueryMsg::ApprovedForAll {
+            owner: app.better.to_string(),
+            include_expired: None,
+            start_after: None,
+            limit: None,
+        })
+        .unwrap()
+        .operators;
+    assert_eq!(operators.len(), 1);
+    assert_eq!(operators[0].spender, operator.as_str());
+
+    send_from(&operator, 100).unwrap();
+    send_from(&app.better, 100).unwrap();
+    assert_eq!(balance(&recipient, 0), Uint128::new(200));
+    assert_eq!(app.query_wallet_count().unwrap(), (3, vec![3, 1]));
+
+    app.execute(
+        &app.better,
+        &ExecuteMsg::RevokeAll {
+            operator: operator.to_string(),
+        },
+        None,
+    )
+    .unwrap();
+    assert!(!is_approved());
+    send_from(&operator, 100).unwrap_err();
+}
+
+#[test]
+fn cw1155_receive_hook() {
+    fn receiver_execute(
+        _: DepsMut,
+        _: Env,
+        _: MessageInfo,
+        msg: Cw1155ReceiverMsg,
+    ) -> StdResult<Response> {
+        let (kind, count) = match msg {
+            Cw1155ReceiverMsg::Receive(_) => ("receive", 1),
+            Cw1155ReceiverMsg::BatchReceive(msg) => ("batch-receive", msg.batch.len()),
+        };
+        Ok(Response::new().add_event(
+            Event::new("receiver")
+                .add_attribute("kind", kind)
+                .add_attribute("count", count.to_string()),
+        ))
+    }
+    fn receiver_instantiate(_: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+    fn receiver_query(_: Deps, _: Env, _: Empty) -> StdResult<Binary> {
+        to_json_binary(&Empty {})
+    }
+
+    let app = Predict::new();
+    let receiver = {
+        let mut inner = app.app.borrow_mut();
+        let code_id = inner.store_code(Box::new(ContractWrapper::new(
+            receiver_execute,
+            receiver_instantiate,
+            receiver_query,
+        )));
+        inner
+            .instantiate_contract(code_id, app.admin.clone(), &Empty {}, &[], "receiver", None)
+            .unwrap()
+    };
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+
+    let token_id = |outcome: u8| format!("{}/{outcome}", app.id);
+    let res = app
+        .execute(
+            &app.better,
+            &ExecuteMsg::SendFrom {
+                from: app.better.to_string(),
+                to: receiver.to_string(),
+                token_id: token_id(0),
+                value: 10u8.into(),
+                msg: Some(Binary::default()),
+            },
+            None,
+        )
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm-receiver")
+            .add_attribute("kind", "receive")
+            .add_attribute("count", "1")
+    ));
+
+    let res = app
+        .execute(
+            &app.better,
+            &ExecuteMsg::BatchSendFrom {
+                from: app.better.to_string(),
+                to: receiver.to_string(),
+                batch: vec![(token_id(0), 10u8.into()), (token_id(1), 20u8.into())],
+                msg: Some(Binary::default()),
+            },
+            None,
+        )
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm-receiver")
+            .add_attribute("kind", "batch-receive")
+            .add_attribute("count", "2")
+    ));
+    assert_eq!(app.query_tokens(&receiver, 1).unwrap(), Token(20u8.into()));
+}
+
+#[test]
+fn native_outcome_tokens() {
+    let mut app = Predict::new();
+    let mut params = app.market_params();
+    params.native_tokens = true;
+
+    // Creating denoms is paid by the contract, so only the admin can ask for it
+    let mut config = test_config();
+    config.permissionless = true;
+    app.execute(&app.admin, &ExecuteMsg::UpdateConfig { config }, None)
+        .unwrap();
+    let bonded = AddMarketParams {
+        dispute_period_seconds: 60 * 60 * 24,
+        ..params.clone()
+    };
+    let err = app.add_market(&app.better, bonded, 1_000).unwrap_err();
+    assert!(matches!(
+        err.downcast::<Error>().unwrap(),
+        Error::NativeTokensAdminOnly
+    ));
+
+    app.add_market(&app.admin, params, 1_000).unwrap();
+    app.id = MarketId(2);
+
+    let market = app.query_latest_market().unwrap();
+    let denoms = market
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.native_denom.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(denoms[1], format!("factory/{}/m2o1", app.contract));
+    let native_balance = |addr: &Addr, outcome: usize| {
+        app.app
+            .borrow()
+            .wrap()
+            .query_balance(addr, &denoms[outcome])
+            .unwrap()
+            .amount
+    };
+    let coin = |outcome: usize, amount: u128| Coin {
+        denom: denoms[outcome].clone(),
+        amount: amount.into(),
+    };
+
+    // Deposits mint coins instead of internal balances
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let tokens = native_balance(&app.better, 0);
+    assert!(!tokens.is_zero());
+    assert_eq!(app.query_tokens(&app.better, 0).unwrap(), Token::zero());
+    assert_eq!(
+        Uint256::from(tokens),
+        app.query_latest_market().unwrap().outcomes[0].minted.0
+    );
+
+    // Withdrawals require the coins, matching the requested amount
+    let withdraw = |amount: u128, coins: &[Coin]| {
+        app.execute_with_coins(
+            &app.better,
+            &ExecuteMsg::Withdraw {
+                id: app.id,
+                outcome: 0.into(),
+                tokens: Token(amount.into()),
+                min_collateral: None,
+            },
+            coins,
+        )
+    };
+    withdraw(100, &[]).unwrap_err();
+    withdraw(100, &[coin(0, 50)]).unwrap_err();
+    let before = app.query_balance(&app.better).unwrap();
+    withdraw(100, &[coin(0, 100)]).unwrap();
+    assert!(app.query_balance(&app.better).unwrap() > before);
+    assert_eq!(native_balance(&app.better, 0), tokens - Uint128::new(100));
+
+    // Complete sets mint and burn coins of every outcome
+    app.execute(&app.better, &ExecuteMsg::MintSet { id: app.id }, Some(500))
+        .unwrap();
+    assert_eq!(native_balance(&app.better, 1), Uint128::new(500));
+    let redeem_set = |coins: &[Coin]| {
+        app.execute_with_coins(
+            &app.better,
+            &ExecuteMsg::RedeemSet {
+                id: app.id,
+                amount: Token(200u16.into()),
+            },
+            coins,
+        )
+    };
+    redeem_set(&[]).unwrap_err();
+    redeem_set(&[coin(0, 200)]).unwrap_err();
+    redeem_set(&[coin(0, 200), coin(1, 200)]).unwrap();
+    assert_eq!(native_balance(&app.better, 1), Uint128::new(300));
+
+    // Winning coins are redeemed for collateral
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    let collect = |coins: &[Coin]| {
+        app.execute_with_coins(&app.better, &ExecuteMsg::Collect { id: app.id }, coins)
+    };
+    collect(&[coin(1, 300)]).unwrap_err();
+    let winning = native_balance(&app.better, 0);
+    let before = app.query_balance(&app.better).unwrap();
+    collect(&[coin(0, winning.u128())]).unwrap();
+    assert_eq!(app.query_balance(&app.better).unwrap(), before + winning);
+    assert_eq!(native_balance(&app.better, 0), Uint128::zero());
+
+    // The house still collects through its internal balance
+    app.collect(&app.house).unwrap();
+}
+
+#[test]
+fn cw20_collateral() {
+    let mut app = Predict::new();
+    let cw20 = {
+        let mut inner = app.app.borrow_mut();
+        let code_id = inner.store_code(Box::new(ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        )));
+        inner
+            .instantiate_contract(
+                code_id,
+                app.admin.clone(),
+                &cw20_base::msg::InstantiateMsg {
+                    name: "Stable".to_owned(),
+                    symbol: "STBL".to_owned(),
+                    decimals: 6,
+                    initial_balances: vec![
+                        Cw20Coin {
+                            address: app.admin.to_string(),
+                            amount: 1_000_000u32.into(),
+                        },
+                        Cw20Coin {
+                            address: app.better.to_string(),
+                            amount: 1_000_000u32.into(),
+                        },
+                    ],
+                    mint: None,
+                    marketing: None,
+                },
+                &[],
+                "cw20",
+                None,
+            )
+            .unwrap()
+    };
+    let send = |app: &Predict, sender: &Addr, amount: u32, msg: &ExecuteMsg| {
+        app.app.borrow_mut().execute_contract(
+            sender.clone(),
+            cw20.clone(),
+            &Cw20ExecuteMsg::Send {
+                contract: app.contract.to_string(),
+                amount: amount.into(),
+                msg: to_json_binary(msg).unwrap(),
+            },
+            &[],
+        )
+    };
+    let cw20_balance = |app: &Predict, addr: &Addr| {
+        app.app
+            .borrow()
+            .wrap()
+            .query_wasm_smart::<Cw20BalanceResponse>(
+                &cw20,
+                &Cw20QueryMsg::Balance {
+                    address: addr.to_string(),
+                },
+            )
+            .unwrap()
+            .balance
+    };
+
+    // Native denoms keep their bare string representation
+    let native = Denom::Native(DENOM.to_owned());
+    assert_eq!(to_json_binary(&native).unwrap().as_slice(), br#""satoshi""#);
+    assert_eq!(
+        cosmwasm_std::from_json::<Denom>(br#"{"native":"satoshi"}"#).unwrap(),
+        native
+    );
+    let schema = cosmwasm_std::to_json_string(&schemars::schema_for!(Denom)).unwrap();
+    // The schema matches the serialization, not the enum layout
+    assert!(schema.contains(r#""anyOf":[{"type":"string"},"#));
+    assert!(schema.contains(r#""required":["cw20"]"#));
+    let cw20_denom = Denom::Cw20(cw20.clone());
+    assert_eq!(
+        cosmwasm_std::from_json::<Denom>(to_json_binary(&cw20_denom).unwrap()).unwrap(),
+        cw20_denom
+    );
+
+    let mut params = app.market_params();
+    params.denom = Denom::Cw20(cw20.clone());
+    params.dispute_period_seconds = 60 * 60 * 24;
+    let add_market = ExecuteMsg::AddMarket {
+        params: params.clone().into(),
+    };
+    send(&app, &app.better, 1_000, &add_market).unwrap_err();
+    app.add_market(&app.admin, params, 1_000).unwrap_err();
+    // CW20 collateral needs to be allowed first
+    send(&app, &app.admin, 1_000, &add_market).unwrap_err();
+    let mut config = test_config();
+    config.collateral.push(CollateralConfig {
+        denom: Denom::Cw20(Addr::unchecked("NOT-AN-ADDRESS")),
+        ..config.collateral[0].clone()
+    });
+    let update_config = |config: &Config| {
+        app.execute(
+            &app.admin,
+            &ExecuteMsg::UpdateConfig {
+                config: config.clone(),
+            },
+            None,
+        )
+    };
+    update_config(&config).unwrap_err();
+    config.collateral[1].denom = Denom::Cw20(cw20.clone());
+    update_config(&config).unwrap();
+    // The sender is only claimed by the CW20 contract, so it doesn't count as the admin
+    send(&app, &app.admin, 1_000, &add_market).unwrap_err();
+    config.permissionless = true;
+    update_config(&config).unwrap();
+    send(&app, &app.admin, 1_000, &add_market).unwrap();
+    app.id = MarketId(2);
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.denom, Denom::Cw20(cw20.clone()));
+    assert_eq!(market.creator_bond.unwrap().creator, app.admin);
+
+    // Native coins are rejected, CW20 deposits are credited to the CW20 sender
+    app.place_bet(&app.better, 0, 1_000).unwrap_err();
+    let deposit = ExecuteMsg::Deposit {
+        id: app.id,
+        outcome: 0.into(),
+        liquidity: Decimal256::zero(),
+        min_tokens: None,
+        referrer: None,
+    };
+    send(&app, &app.better, 1_000, &deposit).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    assert!(!tokens.is_zero());
+
+    // Only collateral-taking messages can be embedded
+    let withdraw = ExecuteMsg::Withdraw {
+        id: app.id,
+        outcome: 0.into(),
+        tokens,
+        min_collateral: None,
+    };
+    send(&app, &app.better, 1, &withdraw).unwrap_err();
+
+    // Payouts are CW20 transfers
+    let before = cw20_balance(&app, &app.better);
+    let half = Token(tokens.0 / Uint256::from(2u8));
+    app.withdraw(&app.better, 0, half).unwrap();
+    assert!(cw20_balance(&app, &app.better) > before);
+
+    app.jump_days(3);
+    app.propose_winner(&app.arbitrator, 0).unwrap();
+    app.jump_days(1);
+    app.finalize_winner().unwrap();
+    let before = cw20_balance(&app, &app.better);
+    app.collect(&app.better).unwrap();
+    assert!(cw20_balance(&app, &app.better) > before);
+    app.collect(&app.house).unwrap();
+    assert!(!cw20_balance(&app, &app.house).is_zero());
+}
+
+#[test]
+fn price_history() {
+    let app = Predict::new();
+    let history = |start_after: Option<Timestamp>, limit: Option<u32>| {
+        app.query::<PriceHistoryResp>(&QueryMsg::PriceHistory {
+            id: app.id,
+            start_after,
+            limit,
+        })
+        .unwrap()
+        .snapshots
+    };
+    let created = app.app.borrow().block_info().time;
+
+    // Trades within the same hour replace the snapshot
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let snapshots = history(None, None);
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(
+        snapshots[0].prices,
+        app.query_latest_market().unwrap().prices()
+    );
+
+    app.app
+        .borrow_mut()
+        .update_block(|block| block.time = block.time.plus_seconds(PRICE_HISTORY_BUCKET_SECONDS));
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+    app.app
+        .borrow_mut()
+        .update_block(|block| block.time = block.time.plus_seconds(PRICE_HISTORY_BUCKET_SECONDS));
+    app.provide(&app.better, 1_000).unwrap();
+
+    let snapshots = history(None, None);
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[0].timestamp, created);
+    assert!(snapshots[1].prices[1] > snapshots[0].prices[1]);
+    assert!(snapshots[2].pool_size.0 > snapshots[1].pool_size.0);
+
+    let page = history(Some(snapshots[0].timestamp), Some(1));
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].timestamp, snapshots[1].timestamp);
+}
+
+#[test]
+fn market_stats() {
+    let app = Predict::new();
+    let stats = app.query_latest_market().unwrap().stats;
+    assert_eq!(stats.trades, 0);
+    assert_eq!(stats.last_trade, None);
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.place_bet(&app.better, 1, 500).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    let simulated = app
+        .query::<SimulateWithdrawResp>(&QueryMsg::SimulateWithdraw {
+            id: app.id,
+            outcome: 0.into(),
+            tokens,
+        })
+        .unwrap();
+    app.withdraw(&app.better, 0, tokens).unwrap();
+
+    // Liquidity is not trading activity
+    app.provide(&app.better, 1_000).unwrap();
+
+    let stats = app.query_latest_market().unwrap().stats;
+    assert_eq!(stats.deposit_volume, Collateral(1_500u16.into()));
+    assert_eq!(stats.deposit_fees, Collateral(15u8.into()));
+    assert_eq!(stats.withdrawal_volume, simulated.funds + simulated.fee);
+    assert_eq!(stats.withdrawal_fees, simulated.fee);
+    assert_eq!(stats.trades, 3);
+    assert_eq!(stats.last_trade, Some(app.app.borrow().block_info().time));
+}
+
+#[test]
+fn position_value() {
+    let app = Predict::new();
+    let position_value = |addr: &Addr| {
+        app.query::<PositionValueResp>(&QueryMsg::PositionValue {
+            id: app.id,
+            addr: addr.to_string(),
+        })
+        .unwrap()
+    };
+    let collateral = |amount: u64| Collateral(amount.into());
+
+    let house = position_value(&app.house);
+    assert_eq!(house.cost_basis, collateral(1_000));
+    assert_eq!(house.realized_pnl, Int256::from(-1_000i64));
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    let value = position_value(&app.better);
+    assert_eq!(value.cost_basis, collateral(1_000));
+    assert_eq!(value.withdrawn, Collateral::zero());
+    assert_eq!(value.realized_pnl, Int256::from(-1_000i64));
+    assert_eq!(
+        value.payout_if_wins,
+        vec![Collateral(tokens.0), Collateral::zero()]
+    );
+
+    // Marked to market by selling all tokens
+    let simulated = app
+        .query::<SimulateWithdrawResp>(&QueryMsg::SimulateWithdraw {
+            id: app.id,
+            outcome: 0.into(),
+            tokens,
+        })
+        .unwrap();
+    assert_eq!(value.value, simulated.funds);
+    assert!(value.total_pnl < Int256::zero());
+
+    // Sales are realized
+    let half = Token(tokens.0 / Uint256::from(2u8));
+    let before = app.query_balance(&app.better).unwrap();
+    app.withdraw(&app.better, 0, half).unwrap();
+    let received = app.query_balance(&app.better).unwrap() - before;
+    let value = position_value(&app.better);
+    assert_eq!(value.withdrawn, Collateral(received.into()));
+    assert_eq!(
+        value.realized_pnl,
+        Int256::from(received) - Int256::from(1_000i64)
+    );
+
+    // Resolved positions are worth their payout until collected
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    let remaining = app.query_tokens(&app.better, 0).unwrap();
+    let value = position_value(&app.better);
+    assert_eq!(value.value, Collateral(remaining.0));
+    app.collect(&app.better).unwrap();
+    let value = position_value(&app.better);
+    assert_eq!(value.value, Collateral::zero());
+    assert_eq!(
+        value.withdrawn,
+        Collateral(Uint256::from(received) + remaining.0)
+    );
+    assert_eq!(value.total_pnl, value.realized_pnl);
+    assert_eq!(
+        value.total_pnl,
+        Int256::from(received) + Int256::try_from(remaining.0).unwrap() - Int256::from(1_000i64)
+    );
+    assert_eq!(value.payout_if_wins, vec![Collateral::zero(); 2]);
+}
+
+#[test]
+fn position_value_transfer() {
+    let app = Predict::new();
+    let other = Addr::unchecked("other");
+    let position_value = |addr: &Addr| {
+        app.query::<PositionValueResp>(&QueryMsg::PositionValue {
+            id: app.id,
+            addr: addr.to_string(),
+        })
+        .unwrap()
+    };
+    let transfer = |amount: Token| {
+        app.execute(
+            &app.better,
+            &ExecuteMsg::TransferTokens {
+                id: app.id,
+                outcome: 0.into(),
+                amount,
+                recipient: other.to_string(),
+            },
+            None,
+        )
+        .unwrap()
+    };
+
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    transfer(Token(tokens.0 / Uint256::from(2u8)));
+    let sender = position_value(&app.better);
+    let recipient = position_value(&other);
+    assert_eq!(
+        sender.cost_basis + recipient.cost_basis,
+        Collateral(1_000u16.into())
+    );
+    assert!(sender.cost_basis.0 >= Uint256::from(499u16));
+    assert!(recipient.cost_basis.0 >= Uint256::from(499u16));
+    assert!(sender.total_pnl < Int256::zero());
+    assert!(recipient.total_pnl < Int256::zero());
+
+    // Transferring the rest moves all of the remaining cost basis
+    transfer(app.query_tokens(&app.better, 0).unwrap());
+    assert!(position_value(&app.better).cost_basis.is_zero());
+    assert_eq!(
+        position_value(&other).cost_basis,
+        Collateral(1_000u16.into())
+    );
+}
 
-    // We try to collect and fail since we do not have any winning
-    // outcome tokens
-    app.collect(&app.better).unwrap_err();
+#[test]
+fn list_holders() {
+    let app = Predict::new();
+    let other = Addr::unchecked("other");
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+    app.execute(
+        &app.better,
+        &ExecuteMsg::TransferTokens {
+            id: app.id,
+            outcome: 1.into(),
+            amount: app.query_tokens(&app.better, 1).unwrap(),
+            recipient: other.to_string(),
+        },
+        None,
+    )
+    .unwrap();
+
+    let holders = |outcome: Option<u8>, start_after: Option<&Addr>, limit: Option<u32>| {
+        app.query::<HoldersResp>(&QueryMsg::Holders {
+            id: app.id,
+            outcome: outcome.map(OutcomeId),
+            start_after: start_after.map(|addr| addr.to_string()),
+            limit,
+        })
+        .unwrap()
+        .holders
+        .into_iter()
+        .map(|holder| holder.addr)
+        .collect::<Vec<_>>()
+    };
 
-    app.collect(&app.house).unwrap();
-    let house_after = app.query_balance(&app.house).unwrap();
     assert_eq!(
-        Uint128::from(1000u16) + better_before - better_after,
-        house_after
+        holders(None, None, None),
+        vec![app.better.clone(), app.house.clone(), other.clone()]
+    );
+    assert_eq!(holders(None, None, Some(1)), vec![app.better.clone()]);
+    assert_eq!(
+        holders(None, Some(&app.better), Some(1)),
+        vec![app.house.clone()]
+    );
+    assert_eq!(
+        holders(Some(0), None, None),
+        vec![app.better.clone(), app.house.clone()]
+    );
+    assert_eq!(
+        holders(Some(1), None, None),
+        vec![app.house.clone(), other.clone()]
+    );
+
+    // Counts match the wallet counters on the market
+    let (total, outcomes) = app.query_wallet_count().unwrap();
+    assert_eq!(
+        holders(None, None, None).len(),
+        usize::try_from(total).unwrap()
+    );
+    assert_eq!(
+        holders(Some(0), None, None).len(),
+        usize::try_from(outcomes[0]).unwrap()
     );
+    assert_eq!(
+        holders(Some(1), None, None).len(),
+        usize::try_from(outcomes[1]).unwrap()
+    );
+
+    app.query::<HoldersResp>(&QueryMsg::Holders {
+        id: app.id,
+        outcome: Some(OutcomeId(2)),
+        start_after: None,
+        limit: None,
+    })
+    .unwrap_err();
 }
 
 #[test]
-fn winning_bet() {
+fn backfill_wallet_markets() {
+    let app = Predict::new();
+    app.place_bet(&app.better, 0, 1_000).unwrap();
+    let backfill = |sender: &Addr, limit: u32| {
+        app.execute(sender, &ExecuteMsg::BackfillWalletMarkets { limit }, None)
+            .map_err(|err| err.downcast::<Error>().unwrap())
+    };
+    let positions = |addr: &Addr| {
+        app.query::<WalletPositionsResp>(&QueryMsg::WalletPositions {
+            addr: addr.to_string(),
+            start_after: None,
+            limit: None,
+        })
+        .unwrap()
+        .positions
+        .len()
+    };
+    // New contracts index every holder from the start
+    assert!(matches!(
+        backfill(&app.admin, 10),
+        Err(Error::WalletMarketsBackfilled)
+    ));
+
+    // Simulate holders from before the index existed
+    {
+        let mut inner = app.app.borrow_mut();
+        // Contract storage is namespaced by cw-multi-test
+        let mut prefix = vec![];
+        for namespace in [
+            b"wasm".to_vec(),
+            format!("contract_data/{}", app.contract).into_bytes(),
+        ] {
+            prefix.extend(u16::try_from(namespace.len()).unwrap().to_be_bytes());
+            prefix.extend(namespace);
+        }
+        inner
+            .storage_mut()
+            .remove(&[prefix.as_slice(), WALLET_MARKETS_BACKFILL.as_slice()].concat());
+        for addr in [&app.better, &app.house] {
+            let key = WALLET_MARKETS.key((addr, app.id));
+            inner
+                .storage_mut()
+                .remove(&[prefix.as_slice(), &key].concat());
+        }
+        let code_id = inner.contract_data(&app.contract).unwrap().code_id;
+        inner
+            .migrate_contract(
+                app.admin.clone(),
+                app.contract.clone(),
+                &MigrateMsg {},
+                code_id,
+            )
+            .unwrap();
+    }
+    assert_eq!(positions(&app.better), 0);
+    assert_eq!(positions(&app.house), 0);
+
+    backfill(&app.better, 10).unwrap_err();
+    // A batch of zero makes no progress
+    backfill(&app.admin, 0).unwrap();
+    backfill(&app.admin, 1).unwrap();
+    backfill(&app.admin, 1).unwrap();
+    // The batch ends early once every holder is indexed
+    backfill(&app.admin, 1).unwrap();
+    assert!(matches!(
+        backfill(&app.admin, 1),
+        Err(Error::WalletMarketsBackfilled)
+    ));
+    assert_eq!(positions(&app.better), 1);
+    assert_eq!(positions(&app.house), 1);
+}
+
+#[test]
+fn wallet_positions() {
     let app = Predict::new();
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+    app.add_market(&app.admin, app.market_params(), 1_000)
+        .unwrap();
+
+    let positions = |addr: &Addr, start_after: Option<u32>, limit: Option<u32>| {
+        app.query::<WalletPositionsResp>(&QueryMsg::WalletPositions {
+            addr: addr.to_string(),
+            start_after: start_after.map(MarketId),
+            limit,
+        })
+        .unwrap()
+        .positions
+    };
+    let ids = |positions: &[WalletPosition]| positions.iter().map(|p| p.id.0).collect::<Vec<_>>();
+
+    assert_eq!(ids(&positions(&app.better, None, None)), Vec::<u32>::new());
+    assert_eq!(ids(&positions(&app.house, None, None)), vec![1, 2, 3]);
+    assert_eq!(ids(&positions(&app.house, Some(1), Some(1))), vec![2]);
 
     app.place_bet(&app.better, 0, 1_000).unwrap();
+    app.execute(
+        &app.better,
+        &ExecuteMsg::Deposit {
+            id: MarketId(3),
+            outcome: 1.into(),
+            liquidity: Decimal256::zero(),
+            min_tokens: None,
+            referrer: None,
+        },
+        Some(1_000),
+    )
+    .unwrap();
+    let wallet = positions(&app.better, None, None);
+    assert_eq!(ids(&wallet), vec![1, 3]);
+    assert!(!wallet[0].value.is_zero());
+    assert!(!wallet[0].claimable);
+
+    // Selling everything removes the market from the wallet
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    app.withdraw(&app.better, 0, tokens).unwrap();
+    assert_eq!(ids(&positions(&app.better, None, None)), vec![3]);
 
+    app.place_bet(&app.better, 0, 1_000).unwrap();
     app.jump_days(3);
-
-    let better_before = app.query_balance(&app.better).unwrap();
     app.set_winner(&app.arbitrator, 0).unwrap();
+    let wallet = positions(&app.better, None, None);
+    assert_eq!(wallet[0].status, MarketStatus::Resolved);
+    assert_eq!(
+        wallet[0].value.0,
+        app.query_tokens(&app.better, 0).unwrap().0
+    );
+    assert!(wallet[0].claimable);
+    assert!(!wallet[0].claimed);
 
     app.collect(&app.better).unwrap();
-    app.collect(&app.better).unwrap_err();
-    app.collect(&app.arbitrator).unwrap_err();
-    let better_after = app.query_balance(&app.better).unwrap();
-
-    assert!(better_before < better_after);
-
-    let house_after = app.query_balance(&app.house).unwrap();
-    assert!(Uint128::from(1000u16) > house_after);
+    let wallet = positions(&app.better, None, None);
+    assert_eq!(ids(&wallet), vec![1, 3]);
+    assert!(wallet[0].value.is_zero());
+    assert!(!wallet[0].claimable);
+    assert!(wallet[0].claimed);
+    assert!(!wallet[1].claimable);
 }
 
 #[test]
@@ -605,6 +2864,48 @@ fn provide_liquidity() {
     }
 }
 
+#[test]
+fn withdraw_liquidity() {
+    let app = Predict::new();
+
+    let better_before = app.query_balance(&app.better).unwrap();
+    app.provide(&app.better, 1_000).unwrap();
+    let shares = app.query_holder(&app.better).unwrap().shares;
+    assert_ne!(shares, LpShare::zero());
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.lp_wallets, 2);
+
+    app.withdraw_liquidity(&app.better, LpShare(shares.0 + shares.0))
+        .unwrap_err();
+    app.withdraw_liquidity(&app.better, LpShare::zero())
+        .unwrap_err();
+    app.withdraw_liquidity(&app.better, shares).unwrap();
+
+    let holder = app.query_holder(&app.better).unwrap();
+    assert_eq!(holder.shares, LpShare::zero());
+    let market = app.query_latest_market().unwrap();
+    assert_eq!(market.lp_wallets, 1);
+
+    // Collateral plus complete sets of tokens add up to what was provided, less rounding
+    let better_after = app.query_balance(&app.better).unwrap();
+    let sets = holder.outcomes.iter().copied().min().unwrap();
+    let returned = better_after + Uint128::try_from(sets.0).unwrap();
+    assert!(better_before >= returned);
+    assert!(better_before - returned <= Uint128::from(2u8));
+
+    // The house must always keep some shares
+    let house_shares = app.query_holder(&app.house).unwrap().shares;
+    app.withdraw_liquidity(&app.house, house_shares)
+        .unwrap_err();
+    app.withdraw_liquidity(&app.house, LpShare(house_shares.0 / Uint256::from(2u8)))
+        .unwrap();
+
+    app.jump_days(1);
+    app.provide(&app.better, 1_000).unwrap();
+    let shares = app.query_holder(&app.better).unwrap().shares;
+    app.withdraw_liquidity(&app.better, shares).unwrap_err();
+}
+
 #[test]
 fn market_with_only_one_outcome() {
     let app = Predict::new();
@@ -616,12 +2917,17 @@ fn market_with_only_one_outcome() {
             label: "Yes".to_owned(),
             initial_amount: Token(100u16.into()),
         }],
-        denom: DENOM.to_owned(),
+        denom: Denom::Native(DENOM.to_owned()),
         deposit_fee: "0.01".parse().unwrap(),
         withdrawal_fee: "0.01".parse().unwrap(),
         withdrawal_stop_date: app.app.borrow().block_info().time.plus_days(1),
         deposit_stop_date: app.app.borrow().block_info().time.plus_days(2),
+        dispute_period_seconds: 0,
         house: app.house.clone().into_string(),
+        native_tokens: false,
+        fee_schedule: vec![],
+        resolution: None,
+        scalar: None,
     };
     app.app
         .borrow_mut()
@@ -636,7 +2942,63 @@ fn market_with_only_one_outcome() {
                 amount: 100u16.into(),
             }],
         )
+        .unwrap_err();
+}
+
+#[test]
+fn market_outcome_limits() {
+    let app = Predict::new();
+    let outcomes = |count: usize| {
+        (0..count)
+            .map(|idx| OutcomeDef {
+                label: idx.to_string(),
+                initial_amount: Token(100u16.into()),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut params = app.market_params();
+    params.outcomes = outcomes(MAX_OUTCOMES + 1);
+    app.add_market(&app.admin, params, 1_000).unwrap_err();
+
+    let mut params = app.market_params();
+    params.outcomes = outcomes(MAX_OUTCOMES);
+    app.add_market(&app.admin, params, 1_000).unwrap();
+}
+
+#[test]
+fn three_outcome_market() {
+    let mut app = Predict::new();
+    let mut params = app.market_params();
+    params.outcomes = ["Red", "Green", "Blue"]
+        .into_iter()
+        .map(|label| OutcomeDef {
+            label: label.to_owned(),
+            initial_amount: Token(1_000u16.into()),
+        })
+        .collect();
+    app.add_market(&app.admin, params, 1_000).unwrap();
+    app.id = MarketId(2);
+
+    app.place_bet(&app.better, 2, 1_000).unwrap();
+    app.place_bet(&app.admin, 1, 500).unwrap();
+
+    let better_before = app.query_balance(&app.better).unwrap();
+    let tokens = app.query_tokens(&app.better, 2).unwrap();
+    app.withdraw(&app.better, 2, Token(tokens.0 / Uint256::from(2u8)))
         .unwrap();
+    let better_after = app.query_balance(&app.better).unwrap();
+    assert!(better_after > better_before);
+
+    let tokens = app.query_tokens(&app.better, 2).unwrap();
+    app.withdraw(&app.better, 2, tokens).unwrap();
+    assert_eq!(app.query_tokens(&app.better, 2).unwrap(), Token::zero());
+    assert_eq!(app.query_wallet_count().unwrap(), (2, vec![0, 2, 1]));
+
+    app.jump_days(3);
+    app.set_winner(&app.arbitrator, 1).unwrap();
+    app.collect(&app.admin).unwrap();
+    app.collect(&app.house).unwrap();
 }
 
 #[test]
@@ -702,40 +3064,285 @@ fn cannot_bet_liquidity_of_one() {
         .unwrap_err();
 }
 
+#[test]
+fn slippage_protection() {
+    let app = Predict::new();
+
+    let deposit = |min_tokens: u64| {
+        app.execute(
+            &app.better,
+            &ExecuteMsg::Deposit {
+                id: app.id,
+                outcome: 0.into(),
+                liquidity: Decimal256::zero(),
+                min_tokens: Some(Token(min_tokens.into())),
+                referrer: None,
+            },
+            Some(1_000),
+        )
+        .map_err(|err| err.downcast::<Error>().unwrap())
+    };
+
+    // Buying 1,000 worth of the cheap outcome yields far fewer than 1 million tokens
+    let err = deposit(1_000_000).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::DepositSlippageExceeded { expected, actual }
+            if expected == Token(1_000_000u32.into()) && actual.0 < expected.0
+    ));
+    deposit(1).unwrap();
+
+    let tokens = app.query_tokens(&app.better, 0).unwrap();
+    let withdraw = |min_collateral: u64| {
+        app.execute(
+            &app.better,
+            &ExecuteMsg::Withdraw {
+                id: app.id,
+                outcome: 0.into(),
+                tokens,
+                min_collateral: Some(Collateral(min_collateral.into())),
+            },
+            None,
+        )
+        .map_err(|err| err.downcast::<Error>().unwrap())
+    };
+    let err = withdraw(1_000).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::WithdrawalSlippageExceeded { expected, actual }
+            if expected == Collateral(1_000u16.into()) && actual.0 < expected.0
+    ));
+    withdraw(1).unwrap();
+}
+
+#[test]
+fn simulate_matches_execute() {
+    let app = Predict::new();
+
+    let simulated: SimulateDepositResp = app
+        .query(&QueryMsg::SimulateDeposit {
+            id: app.id,
+            outcome: 0.into(),
+            amount: Collateral(1_000u16.into()),
+            liquidity: "0.1".parse().unwrap(),
+            referred: false,
+        })
+        .unwrap();
+    app.place_bet_with(&app.better, 0, 1_000, "0.1".parse().unwrap())
+        .unwrap();
+    let holder = app.query_holder(&app.better).unwrap();
+    assert_eq!(simulated.tokens, holder.outcomes[0]);
+    assert_eq!(simulated.lp_shares, holder.shares);
+    assert_eq!(simulated.fee, Collateral(10u8.into()));
+    assert_eq!(
+        simulated
+            .probabilities
+            .iter()
+            .fold(Decimal256::zero(), |acc, x| acc + x),
+        Decimal256::one()
+    );
+
+    let simulated: SimulateWithdrawResp = app
+        .query(&QueryMsg::SimulateWithdraw {
+            id: app.id,
+            outcome: 0.into(),
+            tokens: holder.outcomes[0],
+        })
+        .unwrap();
+    let before = app.query_balance(&app.better).unwrap();
+    app.withdraw(&app.better, 0, holder.outcomes[0]).unwrap();
+    let after = app.query_balance(&app.better).unwrap();
+    assert_eq!(Uint256::from(after - before), simulated.funds.0);
+
+    // Simulating does not change the market
+    let market = app.query_latest_market().unwrap();
+    app.query::<SimulateDepositResp>(&QueryMsg::SimulateDeposit {
+        id: app.id,
+        outcome: 1.into(),
+        amount: Collateral(5_000u16.into()),
+        liquidity: Decimal256::zero(),
+        referred: false,
+    })
+    .unwrap();
+    assert_eq!(
+        app.query_latest_market().unwrap().pool_size,
+        market.pool_size
+    );
+
+    app.jump_days(2);
+    app.query::<SimulateDepositResp>(&QueryMsg::SimulateDeposit {
+        id: app.id,
+        outcome: 1.into(),
+        amount: Collateral(5_000u16.into()),
+        liquidity: Decimal256::zero(),
+        referred: false,
+    })
+    .unwrap_err();
+}
+
+#[test]
+fn list_markets() {
+    let app = Predict::new();
+    let mut params = app.market_params();
+    params.withdrawal_stop_date = params.withdrawal_stop_date.plus_days(10);
+    params.deposit_stop_date = params.deposit_stop_date.plus_days(10);
+    app.add_market(&app.admin, params, 1_000).unwrap();
+
+    let markets = |start_after: Option<u32>, limit: Option<u32>, status| {
+        app.query::<MarketsResp>(&QueryMsg::Markets {
+            start_after: start_after.map(MarketId),
+            limit,
+            status,
+        })
+        .unwrap()
+        .markets
+        .into_iter()
+        .map(|market| market.id.0)
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(markets(None, None, None), vec![1, 2]);
+    assert_eq!(markets(None, Some(1), None), vec![1]);
+    assert_eq!(markets(Some(1), None, None), vec![2]);
+    assert_eq!(markets(None, None, Some(MarketStatus::Open)), vec![1, 2]);
+
+    app.jump_days(1);
+    assert_eq!(
+        markets(None, None, Some(MarketStatus::WithdrawalsClosed)),
+        vec![1]
+    );
+    assert_eq!(markets(None, None, Some(MarketStatus::Open)), vec![2]);
+
+    app.jump_days(1);
+    assert_eq!(
+        markets(None, None, Some(MarketStatus::AwaitingResolution)),
+        vec![1]
+    );
+
+    app.set_winner(&app.arbitrator, 0).unwrap();
+    assert_eq!(markets(None, None, Some(MarketStatus::Resolved)), vec![1]);
+    assert_eq!(
+        markets(None, None, Some(MarketStatus::AwaitingResolution)),
+        Vec::<u32>::new()
+    );
+
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::VoidMarket {
+            id: MarketId(2),
+            invalid: false,
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(markets(None, None, Some(MarketStatus::Voided)), vec![2]);
+
+    // Filtered listings stop after a bounded number of markets
+    let mut params = app.market_params();
+    params.withdrawal_stop_date = params.withdrawal_stop_date.plus_days(10);
+    params.deposit_stop_date = params.deposit_stop_date.plus_days(10);
+    for _ in 0..MAX_MARKETS_SCANNED {
+        app.add_market(&app.admin, params.clone(), 1_000).unwrap();
+    }
+    let last = u32::try_from(MAX_MARKETS_SCANNED).unwrap() + 2;
+    app.execute(
+        &app.admin,
+        &ExecuteMsg::VoidMarket {
+            id: MarketId(last),
+            invalid: false,
+        },
+        None,
+    )
+    .unwrap();
+    let page = |start_after: Option<MarketId>| {
+        let MarketsResp {
+            markets,
+            next_start_after,
+        } = app
+            .query(&QueryMsg::Markets {
+                start_after,
+                limit: None,
+                status: Some(MarketStatus::Voided),
+            })
+            .unwrap();
+        (
+            markets
+                .into_iter()
+                .map(|market| market.id.0)
+                .collect::<Vec<_>>(),
+            next_start_after,
+        )
+    };
+    let (ids, next) = page(None);
+    assert_eq!(ids, vec![2]);
+    assert_eq!(next, Some(MarketId(last - 2)));
+    assert_eq!(page(next), (vec![last], None));
+
+    // Full pages point at the last market returned
+    let MarketsResp {
+        markets: _,
+        next_start_after,
+    } = app
+        .query(&QueryMsg::Markets {
+            start_after: None,
+            limit: Some(2),
+            status: None,
+        })
+        .unwrap();
+    assert_eq!(next_start_after, Some(MarketId(2)));
+}
+
+#[test]
+fn query_prices() {
+    let app = Predict::new();
+
+    let prices = |app: &Predict| {
+        app.query::<PricesResp>(&QueryMsg::Prices { id: app.id })
+            .unwrap()
+            .outcomes
+            .into_iter()
+            .map(|outcome| outcome.price)
+            .collect::<Vec<_>>()
+    };
+
+    // The smaller pool is the more likely outcome
+    assert_eq!(
+        prices(&app),
+        vec![
+            Decimal256::from_ratio(9u8, 10u8),
+            Decimal256::from_ratio(1u8, 10u8)
+        ]
+    );
+
+    app.place_bet(&app.better, 1, 1_000).unwrap();
+    let after = prices(&app);
+    assert!(after[1] > "0.1".parse().unwrap());
+    assert_eq!(after[0] + after[1], Decimal256::one());
+}
+
 #[test]
 fn precise_numbers1() {
-    let ts = Timestamp::from_nanos(1_000_000_202);
-    let mut stored = StoredMarket {
-        id: MarketId::one(),
-        title: "ATOM_USDT".to_owned(),
-        description: "Some desc".to_owned(),
-        arbitrator: Addr::unchecked("arbitrator"),
-        outcomes: vec![
+    let mut stored = test_market(
+        vec![
             StoredOutcome {
                 id: OutcomeId(0),
                 label: "Yes".to_owned(),
                 pool_tokens: Token(Uint256::from_u128(1161329)),
                 wallets: 1,
+                native_denom: None,
+                minted: Token::zero(),
             },
             StoredOutcome {
                 id: OutcomeId(1),
                 label: "No".to_owned(),
                 pool_tokens: Token(Uint256::from_u128(1518053)),
                 wallets: 1,
+                native_denom: None,
+                minted: Token::zero(),
             },
         ],
-        denom: DENOM.to_owned(),
-        deposit_fee: "0.01".parse().unwrap(),
-        withdrawal_fee: "0.01".parse().unwrap(),
-        pool_size: Collateral(Uint256::from_u128(10_000_000)),
-        deposit_stop_date: ts.plus_days(2),
-        withdrawal_stop_date: ts.plus_days(1),
-        winner: None,
-        house: Addr::unchecked("house"),
-        total_wallets: 0,
-        lp_shares: LpShare::zero(),
-        lp_wallets: 0,
-    };
+        Collateral(Uint256::from_u128(10_000_000)),
+    );
     let Buy { lp: _, tokens } = stored
         .buy(
             OutcomeId(1),
@@ -744,7 +3351,35 @@ fn precise_numbers1() {
             "0.1".parse().unwrap(),
         )
         .unwrap();
-    assert_eq!(tokens.0, Uint256::from_u128(1578210));
+    // Rounded in the pool's favor
+    assert_eq!(tokens.0, Uint256::from_u128(1578209));
+}
+
+#[test]
+fn cpmm_large_pools_many_outcomes() {
+    // The product of all pools would need more than 512 bits here
+    let pool = Token(Uint256::from_u128(10u128.pow(24)));
+    let funds = Collateral(pool.0);
+    let outcomes = (0..MAX_OUTCOMES)
+        .map(|idx| OutcomeDef {
+            label: idx.to_string(),
+            initial_amount: pool,
+        })
+        .collect();
+    let InitialOutcomes {
+        outcomes,
+        returned: _,
+    } = initial_outcomes(outcomes, funds).unwrap();
+    let mut stored = test_market(outcomes, funds);
+
+    let buy = Collateral(Uint256::from_u128(10u128.pow(22)));
+    let Buy { lp: _, tokens } = stored.buy(OutcomeId(3), buy, Decimal256::zero()).unwrap();
+    // With even odds across 8 outcomes, tokens cost well under one collateral each
+    assert!(tokens.0 > buy.0 * Uint256::from_u128(7));
+
+    let Sell { funds, returned: _ } = stored.sell(OutcomeId(3), tokens).unwrap();
+    assert!(funds.0 <= buy.0);
+    assert!(funds.0 > buy.0 * Decimal256::percent(99));
 }
 
 proptest! {
@@ -771,26 +3406,7 @@ fn test_cpmm_buy_sell(pool_one in 1..1000u32, pool_two in 1..1000u32, buy in 2..
         original_variant *= outcome.pool_tokens.0;
     }
 
-    let ts = Timestamp::from_nanos(1_000_000_202);
-
-    let mut stored = StoredMarket {
-        id: MarketId::one(),
-        title: "ATOM_USDT".to_owned(),
-        description: "Some desc".to_owned(),
-        arbitrator: Addr::unchecked("arbitrator"),
-        outcomes,
-        denom: DENOM.to_owned(),
-        deposit_fee: "0.01".parse().unwrap(),
-        withdrawal_fee: "0.01".parse().unwrap(),
-        pool_size: funds,
-        deposit_stop_date: ts.plus_days(2),
-        withdrawal_stop_date: ts.plus_days(1),
-        winner: None,
-        house: Addr::unchecked("house"),
-        total_wallets: 0,
-        lp_shares: LpShare::zero(),
-        lp_wallets: 0,
-    };
+    let mut stored = test_market(outcomes, funds);
     let yes_id = OutcomeId::from(0);
     let yes_tokens = stored.buy(yes_id, buy, Decimal256::zero()).unwrap();
     let mut mid_variant = Decimal256::one();
@@ -814,6 +3430,87 @@ fn test_cpmm_buy_sell(pool_one in 1..1000u32, pool_two in 1..1000u32, buy in 2..
     // assert!(diff2 < Decimal256::from_ratio(1u32, 10u32), "diff2 == {diff2} is too large");
 }
 
+#[test]
+fn test_prices_sum_to_one(pools in proptest::collection::vec(1..1_000_000u32, 2..8)) {
+    let outcomes = pools
+        .iter()
+        .enumerate()
+        .map(|(idx, pool)| StoredOutcome {
+            id: OutcomeId::try_from(idx).unwrap(),
+            label: idx.to_string(),
+            pool_tokens: Token((*pool).into()),
+            wallets: 0,
+            native_denom: None,
+            minted: Token::zero(),
+        })
+        .collect();
+    let stored = test_market(outcomes, Collateral(1_000_000u32.into()));
+    let prices = stored.prices();
+    let total = prices.iter().fold(Decimal256::zero(), |acc, x| acc + x);
+    assert_eq!(total, Decimal256::one());
+
+    // Smaller pools always have higher prices
+    for i in 0..pools.len() {
+        for j in 0..pools.len() {
+            if pools[i] < pools[j] {
+                assert!(prices[i] >= prices[j]);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cpmm_buy_sell_many_outcomes(
+    pools in proptest::collection::vec(1..1_000_000u32, 3..=8),
+    buy in 1..10_000u32,
+    selected in 0..8usize,
+) {
+    let funds = Collateral((*pools.iter().max().unwrap()).into());
+    let outcomes = pools
+        .iter()
+        .enumerate()
+        .map(|(idx, pool)| OutcomeDef {
+            label: idx.to_string(),
+            initial_amount: Token((*pool).into()),
+        })
+        .collect();
+    let InitialOutcomes { outcomes, returned: _ } = initial_outcomes(outcomes, funds).unwrap();
+    let selected = OutcomeId::try_from(selected % pools.len()).unwrap();
+
+    let mut stored = test_market(outcomes, funds);
+    let invariant = |stored: &StoredMarket| {
+        stored
+            .outcomes
+            .iter()
+            .fold(Uint512::one(), |acc, outcome| acc * Uint512::from(outcome.pool_tokens.0))
+    };
+
+    let old_invariant = invariant(&stored);
+    // Rounding up keeps at least one token in the selected pool
+    let Buy { lp: _, tokens } = stored
+        .buy(selected, Collateral(buy.into()), Decimal256::zero())
+        .unwrap();
+    let mid_invariant = invariant(&stored);
+    assert!(mid_invariant >= old_invariant);
+
+    let pool_size = stored.pool_size;
+    let Sell { funds, returned } = stored.sell(selected, tokens).unwrap();
+    let new_invariant = invariant(&stored);
+    assert!(new_invariant >= mid_invariant);
+
+    // Every token is worth at most one collateral, and all tokens are accounted for
+    assert!(funds.0 <= tokens.0);
+    assert_eq!(stored.pool_size + funds, pool_size);
+    for (idx, returned) in returned.into_iter().enumerate() {
+        if idx == selected.usize() {
+            assert!(returned <= tokens);
+        } else {
+            assert!(returned.is_zero());
+        }
+    }
+    assert!(stored.outcomes.iter().all(|outcome| !outcome.pool_tokens.is_zero()));
+}
+
 #[test]
 fn test_cpmm_add_liquidity(pool_one in 1..1000u32, pool_two in 1..1000u32, liquidity in 1..1000u32) {
     let pool_one_tokens = Token(pool_one.into());
@@ -838,26 +3535,7 @@ fn test_cpmm_add_liquidity(pool_one in 1..1000u32, pool_two in 1..1000u32, liqui
         original_variant *= outcome.pool_tokens.0;
     }
 
-    let ts = Timestamp::from_nanos(1_000_000_202);
-
-    let mut stored = StoredMarket {
-        id: MarketId::one(),
-        title: "ATOM_USDT".to_owned(),
-        description: "Some desc".to_owned(),
-        arbitrator: Addr::unchecked("arbitrator"),
-        outcomes,
-        denom: DENOM.to_owned(),
-        deposit_fee: "0.01".parse().unwrap(),
-        withdrawal_fee: "0.01".parse().unwrap(),
-        pool_size: funds,
-        deposit_stop_date: ts.plus_days(2),
-        withdrawal_stop_date: ts.plus_days(1),
-        winner: None,
-        house: Addr::unchecked("house"),
-        total_wallets: 0,
-        lp_shares: LpShare::zero(),
-        lp_wallets: 0,
-    };
+    let mut stored = test_market(outcomes, funds);
 
     let stats = stored.add_liquidity(Collateral(liquidity.into()));
     for (idx, item) in stats.returned_to_user.iter().enumerate() {
@@ -0,0 +1,104 @@
+//! Minimal tokenfactory messages for issuing outcome tokens as native coins.
+//!
+//! Encoded by hand with prost to avoid pulling in a full chain SDK. The
+//! message layout follows the Osmosis tokenfactory module, which most
+//! CosmWasm chains with tokenfactory support.
+
+use cosmwasm_std::CosmosMsg;
+
+use crate::prelude::*;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct ProtoCoin {
+    #[prost(string, tag = "1")]
+    pub denom: String,
+    #[prost(string, tag = "2")]
+    pub amount: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct MsgCreateDenom {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, tag = "2")]
+    pub subdenom: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct MsgMint {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, optional, tag = "2")]
+    pub amount: Option<ProtoCoin>,
+    #[prost(string, tag = "3")]
+    pub mint_to_address: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct MsgBurn {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, optional, tag = "2")]
+    pub amount: Option<ProtoCoin>,
+    #[prost(string, tag = "3")]
+    pub burn_from_address: String,
+}
+
+pub(crate) const CREATE_DENOM_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgCreateDenom";
+pub(crate) const MINT_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgMint";
+pub(crate) const BURN_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgBurn";
+
+/// Subdenom used for an outcome token, e.g. `m3o1`.
+pub(crate) fn subdenom(market: MarketId, outcome: OutcomeId) -> String {
+    format!("m{market}o{outcome}")
+}
+
+/// Full denom of an outcome token created by this contract.
+pub(crate) fn denom(contract: &Addr, market: MarketId, outcome: OutcomeId) -> String {
+    format!("factory/{contract}/{}", subdenom(market, outcome))
+}
+
+fn stargate(type_url: &str, msg: impl prost::Message) -> CosmosMsg {
+    CosmosMsg::Stargate {
+        type_url: type_url.to_owned(),
+        value: msg.encode_to_vec().into(),
+    }
+}
+
+pub(crate) fn create_denom(contract: &Addr, subdenom: String) -> CosmosMsg {
+    stargate(
+        CREATE_DENOM_TYPE_URL,
+        MsgCreateDenom {
+            sender: contract.to_string(),
+            subdenom,
+        },
+    )
+}
+
+pub(crate) fn mint(contract: &Addr, denom: &str, amount: Token, recipient: &Addr) -> CosmosMsg {
+    stargate(
+        MINT_TYPE_URL,
+        MsgMint {
+            sender: contract.to_string(),
+            amount: Some(ProtoCoin {
+                denom: denom.to_owned(),
+                amount: amount.to_string(),
+            }),
+            mint_to_address: recipient.to_string(),
+        },
+    )
+}
+
+pub(crate) fn burn(contract: &Addr, denom: &str, amount: Token) -> CosmosMsg {
+    stargate(
+        BURN_TYPE_URL,
+        MsgBurn {
+            sender: contract.to_string(),
+            amount: Some(ProtoCoin {
+                denom: denom.to_owned(),
+                amount: amount.to_string(),
+            }),
+            burn_from_address: contract.to_string(),
+        },
+    )
+}
@@ -1,19 +1,52 @@
 use std::{
     fmt::Display,
     ops::{Add, AddAssign, Div, Mul, MulAssign, Sub, SubAssign},
+    str::FromStr,
 };
 
-use cosmwasm_std::{OverflowError, Uint256};
+use cosmwasm_std::{
+    to_json_binary, BankMsg, ConversionOverflowError, CosmosMsg, Int256, OverflowError, Uint256,
+    WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
 use cw_storage_plus::{Key, KeyDeserialize, Prefixer, PrimaryKey};
 
 use crate::prelude::*;
 
-#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug, Copy, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Debug, Copy, PartialEq, Eq, Default)]
 pub struct Collateral(pub Uint256);
 impl Collateral {
+    pub fn zero() -> Self {
+        Collateral(Uint256::zero())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
     pub(crate) fn checked_sub(&self, rhs: Collateral) -> Result<Self, OverflowError> {
         self.0.checked_sub(rhs.0).map(Collateral)
     }
+
+    /// Signed difference between two amounts, for profit and loss.
+    pub(crate) fn signed_sub(self, rhs: Collateral) -> Result<Int256, ConversionOverflowError> {
+        fn to_signed(value: Uint256) -> Result<Int256, ConversionOverflowError> {
+            let bytes = value.to_be_bytes();
+            if bytes[0] >= 0x80 {
+                return Err(ConversionOverflowError::new(
+                    "Uint256",
+                    "Int256",
+                    value.to_string(),
+                ));
+            }
+            Ok(Int256::from_be_bytes(bytes))
+        }
+        if self.0 >= rhs.0 {
+            to_signed(self.0 - rhs.0)
+        } else {
+            Ok(-to_signed(rhs.0 - self.0)?)
+        }
+    }
 }
 
 impl Add for Collateral {
@@ -191,6 +224,155 @@ impl<'a> Prefixer<'a> for MarketId {
     }
 }
 
+/// Asset used as collateral for a market.
+///
+/// Native denoms are serialized as a bare string, matching markets created
+/// before CW20 support, and CW20 tokens as `{"cw20": "<address>"}`.
+/// `{"native": "<denom>"}` is also accepted as input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Denom {
+    Native(String),
+    Cw20(Addr),
+}
+
+impl Display for Denom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Denom::Native(denom) => denom.fmt(f),
+            Denom::Cw20(addr) => write!(f, "cw20:{addr}"),
+        }
+    }
+}
+
+impl Serialize for Denom {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        match self {
+            Denom::Native(denom) => serializer.serialize_str(denom),
+            Denom::Cw20(addr) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("cw20", addr)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Denom {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        struct DenomVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for DenomVisitor {
+            type Value = Denom;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a native denom string, or an object with a native or cw20 key")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, denom: &str) -> std::result::Result<Denom, E> {
+                Ok(Denom::Native(denom.to_owned()))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Denom, A::Error> {
+                let key = map
+                    .next_key::<String>()?
+                    .ok_or_else(|| serde::de::Error::custom("empty denom object"))?;
+                let value = map.next_value::<String>()?;
+                let denom = match key.as_str() {
+                    "native" => Denom::Native(value),
+                    "cw20" => Denom::Cw20(Addr::unchecked(value)),
+                    _ => return Err(serde::de::Error::unknown_field(&key, &["native", "cw20"])),
+                };
+                if map.next_key::<String>()?.is_some() {
+                    return Err(serde::de::Error::custom(
+                        "denom object must have a single key",
+                    ));
+                }
+                Ok(denom)
+            }
+        }
+
+        deserializer.deserialize_any(DenomVisitor)
+    }
+}
+
+impl JsonSchema for Denom {
+    fn schema_name() -> String {
+        "Denom".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        // Mirrors the wire format above, only used for the schema
+        #[derive(JsonSchema)]
+        #[serde(untagged)]
+        #[allow(dead_code)]
+        enum DenomRepr {
+            Native(String),
+            Object(DenomObject),
+        }
+
+        #[derive(JsonSchema)]
+        #[serde(rename_all = "snake_case")]
+        #[allow(dead_code)]
+        enum DenomObject {
+            Native(String),
+            Cw20(String),
+        }
+
+        DenomRepr::json_schema(gen)
+    }
+}
+
+impl Denom {
+    /// Check that a CW20 denom refers to a valid, normalized address.
+    pub(crate) fn validate(&self, api: &dyn Api) -> Result<()> {
+        match self {
+            Denom::Native(_) => Ok(()),
+            Denom::Cw20(addr) => {
+                api.addr_validate(addr.as_str())?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Message sending the given amount of this asset to a wallet.
+    pub(crate) fn send(
+        &self,
+        recipient: impl Into<String>,
+        amount: Collateral,
+    ) -> Result<CosmosMsg> {
+        let amount = Uint128::try_from(amount.0)?;
+        Ok(match self {
+            Denom::Native(denom) => BankMsg::Send {
+                to_address: recipient.into(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            }
+            .into(),
+            Denom::Cw20(addr) => WasmMsg::Execute {
+                contract_addr: addr.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.into(),
+                    amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+}
+
 /// Identifier of the outcome for a market.
 ///
 /// Outcomes are 0-indexed, and we restrict them to a u8. Yes, that means
@@ -230,6 +412,36 @@ impl TryFrom<usize> for OutcomeId {
     }
 }
 
+/// An outcome token as exposed through the cw1155 interface.
+///
+/// Token ids have the form `{market_id}/{outcome_id}`, e.g. `3/1`.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub struct OutcomeToken {
+    pub market: MarketId,
+    pub outcome: OutcomeId,
+}
+
+impl Display for OutcomeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.market, self.outcome)
+    }
+}
+
+impl FromStr for OutcomeToken {
+    type Err = Error;
+
+    fn from_str(token_id: &str) -> Result<Self> {
+        let invalid = || Error::InvalidTokenId {
+            token_id: token_id.to_owned(),
+        };
+        let (market, outcome) = token_id.split_once('/').ok_or_else(invalid)?;
+        Ok(OutcomeToken {
+            market: MarketId(market.parse().map_err(|_| invalid())?),
+            outcome: OutcomeId(outcome.parse().map_err(|_| invalid())?),
+        })
+    }
+}
+
 #[derive(
     Clone, Serialize, Deserialize, JsonSchema, Debug, Copy, PartialEq, Eq, PartialOrd, Ord,
 )]
@@ -264,6 +476,12 @@ impl Sub for LpShare {
     }
 }
 
+impl SubAssign for LpShare {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
 impl Mul<Decimal256> for LpShare {
     type Output = LpShare;
 
@@ -1,52 +1,93 @@
 use crate::prelude::*;
 
 #[must_use]
+#[allow(clippy::enum_variant_names)]
 pub enum Funds {
     NoFunds,
-    Funds { denom: String, amount: Uint128 },
+    /// A single asset, either a native coin or CW20 tokens sent through the receive hook
+    Funds {
+        denom: Denom,
+        amount: Uint128,
+    },
+    /// Several coins were attached, only valid for outcome tokens
+    Multiple(Vec<Coin>),
 }
 
 impl Funds {
     pub fn from_message_info(info: &MessageInfo) -> Result<Self> {
-        let mut iter = info.funds.iter();
-        let Coin { denom, amount } = match iter.next() {
-            Some(coin) => coin,
-            None => return Ok(Funds::NoFunds),
-        };
-        if iter.next().is_some() {
-            Err(Error::MultipleAssetsProvided)
-        } else {
-            Ok(Funds::Funds {
-                denom: denom.clone(),
+        match info.funds.as_slice() {
+            [] => Ok(Funds::NoFunds),
+            [Coin { denom, amount }] => Ok(Funds::Funds {
+                denom: Denom::Native(denom.clone()),
                 amount: *amount,
-            })
+            }),
+            coins => Ok(Funds::Multiple(coins.to_vec())),
         }
     }
 
     pub fn require_none(self) -> Result<()> {
         match self {
             Funds::NoFunds => Ok(()),
-            Funds::Funds { denom, amount } => Err(Error::UnexpectedFunds { denom, amount }),
+            Funds::Funds { denom, amount } => Err(Error::UnexpectedFunds {
+                denom: denom.to_string(),
+                amount,
+            }),
+            Funds::Multiple(_) => Err(Error::MultipleAssetsProvided),
         }
     }
 
-    pub fn require_funds(self, required_denom: &str) -> Result<Collateral> {
+    pub fn require_funds(self, required_denom: &Denom) -> Result<Collateral> {
         match self {
             Funds::NoFunds => Err(Error::MissingRequiredFunds {
-                denom: required_denom.to_owned(),
+                denom: required_denom.to_string(),
             }),
             Funds::Funds { denom, amount } => {
-                if denom == required_denom {
+                if denom == *required_denom {
                     Ok(Collateral(amount.into()))
                 } else {
                     Err(Error::IncorrectFundsDenom {
-                        actual_denom: denom,
+                        actual_denom: denom.to_string(),
                         amount,
-                        required_denom: required_denom.to_owned(),
+                        required_denom: required_denom.to_string(),
                     })
                 }
             }
+            Funds::Multiple(_) => Err(Error::MultipleAssetsProvided),
+        }
+    }
+
+    /// Native outcome tokens attached for the given market, indexed by outcome.
+    pub fn require_outcome_tokens(self, market: &StoredMarket) -> Result<Vec<Token>> {
+        let coins = match self {
+            Funds::NoFunds => vec![],
+            Funds::Funds {
+                denom: Denom::Native(denom),
+                amount,
+            } => vec![Coin { denom, amount }],
+            Funds::Funds {
+                denom: denom @ Denom::Cw20(_),
+                ..
+            } => {
+                return Err(Error::NotAnOutcomeDenom {
+                    id: market.id,
+                    denom: denom.to_string(),
+                })
+            }
+            Funds::Multiple(coins) => coins,
+        };
+        let mut tokens = vec![Token::zero(); market.outcomes.len()];
+        for Coin { denom, amount } in coins {
+            let idx = market
+                .outcomes
+                .iter()
+                .position(|outcome| outcome.native_denom.as_ref() == Some(&denom))
+                .ok_or(Error::NotAnOutcomeDenom {
+                    id: market.id,
+                    denom,
+                })?;
+            tokens[idx] += Token(amount.into());
         }
+        Ok(tokens)
     }
 }
 